@@ -0,0 +1,245 @@
+use crate::components::*;
+use crate::AppState;
+use bevy::prelude::*;
+
+/// Size of a single frame in `player_sheet.png`.
+const PLAYER_FRAME_SIZE: f32 = 32.0;
+const PLAYER_SHEET_COLUMNS: usize = 4;
+const PLAYER_SHEET_ROWS: usize = 8;
+/// How long the shooting pose is held after firing, in seconds.
+const SHOOT_POSE_DURATION: f32 = 0.2;
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_startup_system(setup_player_sprite_sheet.system())
+			.add_system_set(
+				SystemSet::on_update(AppState::Game).with_system(animate_player.system()),
+			)
+			.add_system_set(
+				SystemSet::on_enter(AppState::GameOver).with_system(play_death_animation.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::GameOver).with_system(animate_player.system()),
+			);
+	}
+}
+
+pub struct PlayerSpriteSheet(pub Handle<TextureAtlas>);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Facing {
+	Down,
+	Up,
+	Left,
+	Right,
+}
+
+impl Facing {
+	/// Picks the facing closest to `direction`, or `None` for a zero vector.
+	fn from_direction(direction: Vec2) -> Option<Self> {
+		if direction == Vec2::ZERO {
+			None
+		} else if direction.x.abs() > direction.y.abs() {
+			Some(if direction.x < 0.0 {
+				Facing::Left
+			} else {
+				Facing::Right
+			})
+		} else {
+			Some(if direction.y < 0.0 {
+				Facing::Down
+			} else {
+				Facing::Up
+			})
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AnimationState {
+	Idle,
+	Walk,
+	Shoot,
+	Hurt,
+	Die,
+}
+
+/// A row of frames in the sprite sheet.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Clip {
+	row: u32,
+	frames: u32,
+	frame_time: f32,
+	looping: bool,
+}
+
+impl Clip {
+	const IDLE: Clip = Clip::new(0, 2, 0.5, true);
+	const SHOOT: Clip = Clip::new(5, 2, 0.1, false);
+	const HURT: Clip = Clip::new(6, 2, 0.1, true);
+	const DIE: Clip = Clip::new(7, 4, 0.2, false);
+
+	const fn new(row: u32, frames: u32, frame_time: f32, looping: bool) -> Self {
+		Clip {
+			row,
+			frames,
+			frame_time,
+			looping,
+		}
+	}
+
+	const fn walk(facing: Facing) -> Self {
+		let row = match facing {
+			Facing::Down => 1,
+			Facing::Up => 2,
+			Facing::Left => 3,
+			Facing::Right => 4,
+		};
+		Clip::new(row, 4, 0.12, true)
+	}
+
+	/// The first frame of the walk cycle, used to show idle and shooting poses in directions the
+	/// sheet only has front views for.
+	const fn standing(facing: Facing) -> Self {
+		let walk = Clip::walk(facing);
+		Clip::new(walk.row, 1, walk.frame_time, false)
+	}
+
+	fn for_state(state: AnimationState, facing: Facing) -> Self {
+		match (state, facing) {
+			(AnimationState::Idle, Facing::Down) => Clip::IDLE,
+			(AnimationState::Shoot, Facing::Down) => Clip::SHOOT,
+			(AnimationState::Idle, facing) | (AnimationState::Shoot, facing) => {
+				Clip::standing(facing)
+			}
+			(AnimationState::Walk, facing) => Clip::walk(facing),
+			(AnimationState::Hurt, _) => Clip::HURT,
+			(AnimationState::Die, _) => Clip::DIE,
+		}
+	}
+}
+
+/// Animation state machine of the player, driven by its [`MoveDirection`] and aim.
+pub struct PlayerAnimation {
+	state: AnimationState,
+	facing: Facing,
+	clip: Clip,
+	frame: u32,
+	frame_elapsed: f32,
+	/// Time left to hold the shooting pose.
+	shooting: f32,
+	dead: bool,
+}
+
+impl Default for PlayerAnimation {
+	fn default() -> Self {
+		PlayerAnimation {
+			state: AnimationState::Idle,
+			facing: Facing::Down,
+			clip: Clip::IDLE,
+			frame: 0,
+			frame_elapsed: 0.0,
+			shooting: 0.0,
+			dead: false,
+		}
+	}
+}
+
+impl PlayerAnimation {
+	/// Turns the player toward the aim direction and plays the shooting animation.
+	pub fn shoot(&mut self, aim: Vec2) {
+		if let Some(facing) = Facing::from_direction(aim) {
+			self.facing = facing;
+		}
+		self.shooting = SHOOT_POSE_DURATION;
+	}
+
+	fn update(&mut self, delta: f32, movement: Vec2, hurt: bool) {
+		self.shooting = (self.shooting - delta).max(0.0);
+
+		let state = if self.dead {
+			AnimationState::Die
+		} else if hurt {
+			AnimationState::Hurt
+		} else if self.shooting > 0.0 {
+			AnimationState::Shoot
+		} else if movement != Vec2::ZERO {
+			AnimationState::Walk
+		} else {
+			AnimationState::Idle
+		};
+		// Keep facing the aim direction while the shooting pose is held.
+		if state == AnimationState::Walk {
+			if let Some(facing) = Facing::from_direction(movement) {
+				self.facing = facing;
+			}
+		}
+
+		let clip = Clip::for_state(state, self.facing);
+		if state != self.state || clip != self.clip {
+			self.state = state;
+			self.clip = clip;
+			self.frame = 0;
+			self.frame_elapsed = 0.0;
+			return;
+		}
+
+		self.frame_elapsed += delta;
+		while self.frame_elapsed >= clip.frame_time {
+			self.frame_elapsed -= clip.frame_time;
+			self.frame = if clip.looping {
+				(self.frame + 1) % clip.frames
+			} else {
+				(self.frame + 1).min(clip.frames - 1)
+			};
+		}
+	}
+
+	fn sprite_index(&self) -> u32 {
+		self.clip.row * PLAYER_SHEET_COLUMNS as u32 + self.frame
+	}
+}
+
+fn setup_player_sprite_sheet(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+	let texture = asset_server.load("player_sheet.png");
+	let atlas = TextureAtlas::from_grid(
+		texture,
+		Vec2::new(PLAYER_FRAME_SIZE, PLAYER_FRAME_SIZE),
+		PLAYER_SHEET_COLUMNS,
+		PLAYER_SHEET_ROWS,
+	);
+	commands.insert_resource(PlayerSpriteSheet(texture_atlases.add(atlas)));
+}
+
+fn animate_player(
+	time: Res<Time>,
+	mut q: Query<
+		(
+			&MoveDirection,
+			Option<&Respawning>,
+			&mut PlayerAnimation,
+			&mut TextureAtlasSprite,
+		),
+		With<Player>,
+	>,
+) {
+	for (movement, respawning, mut animation, mut sprite) in q.iter_mut() {
+		animation.update(time.delta_seconds(), movement.0, respawning.is_some());
+		let index = animation.sprite_index();
+		if sprite.index != index {
+			sprite.index = index;
+		}
+	}
+}
+
+fn play_death_animation(mut q: Query<&mut PlayerAnimation, With<Player>>) {
+	for mut animation in q.iter_mut() {
+		animation.dead = true;
+	}
+}
@@ -0,0 +1,594 @@
+use crate::components::*;
+use crate::settings::Settings;
+use crate::AppState;
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::math::{Vec3Swizzles, Vec4Swizzles};
+use bevy::prelude::*;
+use bevy::render::camera::Camera;
+
+/// Smallest camera scale, i.e. the closest the camera can zoom in.
+const MIN_ZOOM: f32 = 0.1;
+/// Largest camera scale, i.e. the farthest the camera can zoom out.
+const MAX_ZOOM: f32 = 1.0;
+/// Relative scale change per scrolled line.
+const ZOOM_STEP: f32 = 0.1;
+/// Scroll distance in pixels that counts as a single line on touchpads.
+const PIXELS_PER_LINE: f32 = 20.0;
+/// How quickly the scale approaches the target. Higher is snappier.
+const ZOOM_SMOOTHING: f32 = 10.0;
+/// How quickly the camera catches up with the player. Higher is snappier.
+const FOLLOW_SMOOTHING: f32 = 5.0;
+/// Distance the player can move away from the camera center before the camera follows.
+const FOLLOW_DEAD_ZONE: f32 = TILE_SIZE;
+/// Space kept around the players when zooming out to fit all of them, in world units.
+const FRAMING_MARGIN: f32 = 4.0 * TILE_SIZE;
+/// Largest shake offset, in screen pixels.
+const MAX_SHAKE_OFFSET: f32 = 12.0;
+/// Largest shake rotation, in radians.
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+/// Trauma lost per second.
+const TRAUMA_DECAY: f32 = 1.5;
+/// How fast the shake oscillates.
+const SHAKE_FREQUENCY: f32 = 25.0;
+/// How fast the editor camera moves, in screen pixels per second.
+const PAN_SPEED: f32 = 600.0;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<CursorWorldPosition>()
+			.add_startup_system(setup_camera.system())
+			.add_system_to_stage(CoreStage::PreUpdate, update_cursor_world_position.system())
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(camera_unshake.system().label(CameraSystem::Unshake))
+					.with_system(
+						camera_input
+							.system()
+							.label(CameraSystem::Input)
+							.after(CameraSystem::Unshake),
+					)
+					.with_system(
+						camera_follow
+							.system()
+							.label(CameraSystem::Follow)
+							.after(CameraSystem::Input),
+					)
+					.with_system(
+						camera_zoom
+							.system()
+							.label(CameraSystem::Zoom)
+							.after(CameraSystem::Follow),
+					)
+					.with_system(
+						camera_clamp
+							.system()
+							.label(CameraSystem::Clamp)
+							.after(CameraSystem::Zoom),
+					)
+					.with_system(camera_shake.system().after(CameraSystem::Clamp)),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Editor)
+					.with_system(camera_input.system().label(CameraSystem::Input))
+					.with_system(
+						camera_pan
+							.system()
+							.label(CameraSystem::Follow)
+							.after(CameraSystem::Input),
+					)
+					.with_system(camera_zoom.system().after(CameraSystem::Follow)),
+			);
+	}
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+enum CameraSystem {
+	Unshake,
+	Input,
+	Follow,
+	Zoom,
+	Clamp,
+}
+
+pub struct CameraController {
+	/// Scale the camera is smoothly zooming towards.
+	pub target_scale: f32,
+	/// Smallest scale that fits all players on the screen. Overrides a closer `target_scale`.
+	framing_scale: f32,
+	/// Cursor offset from the screen center that stays in place while zooming.
+	zoom_anchor: Option<Vec2>,
+}
+
+impl CameraController {
+	fn new(scale: f32) -> Self {
+		CameraController {
+			target_scale: scale,
+			framing_scale: 0.0,
+			zoom_anchor: None,
+		}
+	}
+}
+
+/// Trauma based camera shake. The shake grows with the square of the trauma, so small hits
+/// barely move the screen while big ones stack up.
+#[derive(Default)]
+pub struct CameraShake {
+	trauma: f32,
+	/// Offset applied in the last frame, removed before the camera moves again.
+	offset: Vec2,
+}
+
+impl CameraShake {
+	pub fn add_trauma(&mut self, amount: f32) {
+		self.trauma = (self.trauma + amount).min(1.0);
+	}
+}
+
+/// Position of the mouse cursor in the world coordinates of the [`MainCamera`], or `None` if the
+/// cursor is outside of the camera's window. Updated before the [`CoreStage::Update`] stage, so it
+/// matches what was rendered in the last frame.
+#[derive(Debug, Default)]
+pub struct CursorWorldPosition(pub Option<Vec2>);
+
+/// Converts a position in logical window pixels, with the origin in the bottom left corner, to the
+/// world coordinates seen through the camera.
+pub fn screen_to_world(
+	window: &Window,
+	camera: &Camera,
+	camera_transform: &GlobalTransform,
+	position: Vec2,
+) -> Vec2 {
+	let size = Vec2::new(window.width(), window.height());
+	let ndc = position / size * 2.0 - Vec2::ONE;
+	let world = camera_transform.compute_matrix()
+		* camera.projection_matrix.inverse()
+		* ndc.extend(0.0).extend(1.0);
+	world.xy()
+}
+
+/// The inverse of [`screen_to_world`].
+pub fn world_to_screen(
+	window: &Window,
+	camera: &Camera,
+	camera_transform: &GlobalTransform,
+	position: Vec2,
+) -> Vec2 {
+	let size = Vec2::new(window.width(), window.height());
+	let ndc = camera.projection_matrix
+		* camera_transform.compute_matrix().inverse()
+		* position.extend(0.0).extend(1.0);
+	(ndc.xy() + Vec2::ONE) / 2.0 * size
+}
+
+fn setup_camera(mut commands: Commands) {
+	let scale = 0.3;
+	commands
+		.spawn_bundle(OrthographicCameraBundle {
+			transform: Transform {
+				scale: Vec3::new(scale, scale, 1.0),
+				..Default::default()
+			},
+			..OrthographicCameraBundle::new_2d()
+		})
+		.insert(MainCamera)
+		.insert(CameraController::new(scale))
+		.insert(CameraShake::default());
+}
+
+fn update_cursor_world_position(
+	windows: Res<Windows>,
+	mut cursor: ResMut<CursorWorldPosition>,
+	cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+	cursor.0 = cameras
+		.iter()
+		.next()
+		.and_then(|(camera, camera_transform)| {
+			let window = windows.get(camera.window)?;
+			let position = window.cursor_position()?;
+			Some(screen_to_world(window, camera, camera_transform, position))
+		});
+}
+
+fn camera_input(
+	mut scroll_events: EventReader<MouseWheel>,
+	windows: Res<Windows>,
+	mut q: Query<&mut CameraController, With<MainCamera>>,
+) {
+	let lines = scroll_events
+		.iter()
+		.map(|scroll_event| match scroll_event.unit {
+			MouseScrollUnit::Line => scroll_event.y,
+			MouseScrollUnit::Pixel => scroll_event.y / PIXELS_PER_LINE,
+		})
+		.sum::<f32>();
+	if lines == 0.0 {
+		return;
+	}
+
+	// Offset the cursor from the left bottom origin to the screen center.
+	let anchor = windows.get_primary().and_then(|window| {
+		let size = Vec2::new(window.width(), window.height());
+		window.cursor_position().map(|pos| pos - size / 2.0)
+	});
+
+	for mut controller in q.iter_mut() {
+		controller.target_scale =
+			(controller.target_scale * (1.0 + ZOOM_STEP).powf(lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+		controller.zoom_anchor = anchor;
+	}
+}
+
+/// Follows the player, or the middle of all players in co-op while zooming out to fit them.
+fn camera_follow(
+	time: Res<Time>,
+	windows: Res<Windows>,
+	mut q: QuerySet<(
+		Query<&Transform, With<Player>>,
+		Query<(&mut Transform, &mut CameraController), With<MainCamera>>,
+	)>,
+) {
+	let players = q
+		.q0()
+		.iter()
+		.map(|transform| transform.translation.xy())
+		.collect::<Vec<_>>();
+	let (min, max) = match players.first() {
+		Some(first) => players
+			.iter()
+			.fold((*first, *first), |(min, max), position| {
+				(min.min(*position), max.max(*position))
+			}),
+		None => return,
+	};
+	let player_position = (min + max) / 2.0;
+	let framing_scale = match windows.get_primary() {
+		Some(window) if players.len() > 1 => {
+			let needed = (max - min + Vec2::splat(2.0 * FRAMING_MARGIN))
+				/ Vec2::new(window.width(), window.height());
+			needed.max_element().min(MAX_ZOOM)
+		}
+		_ => 0.0,
+	};
+
+	let t = 1.0 - (-FOLLOW_SMOOTHING * time.delta_seconds()).exp();
+	for (mut camera_transform, mut controller) in q.q1_mut().iter_mut() {
+		if controller.framing_scale != framing_scale {
+			controller.framing_scale = framing_scale;
+		}
+		let camera_position = camera_transform.translation.xy();
+		// Only follow the part of the offset that sticks out of the dead zone.
+		let offset = player_position - camera_position;
+		let outside_dead_zone =
+			offset.signum() * (offset.abs() - Vec2::splat(FOLLOW_DEAD_ZONE)).max(Vec2::ZERO);
+		let target = camera_position + outside_dead_zone;
+		let position = camera_position.lerp(target, t);
+		camera_transform.translation.x = position.x;
+		camera_transform.translation.y = position.y;
+	}
+}
+
+fn camera_pan(
+	time: Res<Time>,
+	kb_input: Res<Input<KeyCode>>,
+	mut q: Query<&mut Transform, With<MainCamera>>,
+) {
+	// Keep the camera still while using the editor shortcuts.
+	if kb_input.pressed(KeyCode::LControl) || kb_input.pressed(KeyCode::RControl) {
+		return;
+	}
+	let pressed = |keys: [KeyCode; 2]| keys.iter().any(|key| kb_input.pressed(*key)) as i32 as f32;
+	let direction = Vec2::new(
+		pressed([KeyCode::D, KeyCode::Right]) - pressed([KeyCode::A, KeyCode::Left]),
+		pressed([KeyCode::W, KeyCode::Up]) - pressed([KeyCode::S, KeyCode::Down]),
+	);
+	if direction == Vec2::ZERO {
+		return;
+	}
+	for mut camera_transform in q.iter_mut() {
+		let offset = direction * PAN_SPEED * camera_transform.scale.x * time.delta_seconds();
+		camera_transform.translation.x += offset.x;
+		camera_transform.translation.y += offset.y;
+	}
+}
+
+fn camera_zoom(
+	time: Res<Time>,
+	mut q: Query<(&mut Transform, &mut CameraController), With<MainCamera>>,
+) {
+	let t = 1.0 - (-ZOOM_SMOOTHING * time.delta_seconds()).exp();
+	for (mut camera_transform, mut controller) in q.iter_mut() {
+		let scale = camera_transform.scale.x;
+		let target_scale = controller.target_scale.max(controller.framing_scale);
+		if scale == target_scale {
+			continue;
+		}
+		let mut new_scale = scale + (target_scale - scale) * t;
+		if (target_scale - new_scale).abs() < 0.001 {
+			new_scale = target_scale;
+		}
+
+		// Keep the world point under the anchor in place: it sits at `translation + anchor * scale`.
+		if let Some(anchor) = controller.zoom_anchor {
+			let shift = anchor * (scale - new_scale);
+			camera_transform.translation.x += shift.x;
+			camera_transform.translation.y += shift.y;
+		}
+		if new_scale == target_scale {
+			controller.zoom_anchor = None;
+		}
+
+		camera_transform.scale.x = new_scale;
+		camera_transform.scale.y = new_scale;
+	}
+}
+
+fn camera_clamp(
+	windows: Res<Windows>,
+	level: Res<Level>,
+	mut q: Query<&mut Transform, With<MainCamera>>,
+) {
+	let window = match windows.get_primary() {
+		Some(window) => window,
+		None => return,
+	};
+	let (level_min, level_max) = level.bounds();
+	for mut camera_transform in q.iter_mut() {
+		let half_view =
+			Vec2::new(window.width(), window.height()) / 2.0 * camera_transform.scale.xy();
+		let min = level_min + half_view;
+		let max = level_max - half_view;
+		let position = camera_transform.translation.xy();
+		// Center the level on the axes where it is smaller than the view.
+		let clamped = Vec2::new(
+			if min.x > max.x {
+				(level_min.x + level_max.x) / 2.0
+			} else {
+				position.x.clamp(min.x, max.x)
+			},
+			if min.y > max.y {
+				(level_min.y + level_max.y) / 2.0
+			} else {
+				position.y.clamp(min.y, max.y)
+			},
+		);
+		camera_transform.translation.x = clamped.x;
+		camera_transform.translation.y = clamped.y;
+	}
+}
+
+fn camera_unshake(mut q: Query<(&mut Transform, &mut CameraShake), With<MainCamera>>) {
+	for (mut camera_transform, mut shake) in q.iter_mut() {
+		camera_transform.translation.x -= shake.offset.x;
+		camera_transform.translation.y -= shake.offset.y;
+		camera_transform.rotation = Quat::IDENTITY;
+		shake.offset = Vec2::ZERO;
+	}
+}
+
+fn camera_shake(
+	time: Res<Time>,
+	settings: Res<Settings>,
+	mut q: Query<(&mut Transform, &mut CameraShake), With<MainCamera>>,
+) {
+	for (mut camera_transform, mut shake) in q.iter_mut() {
+		shake.trauma = (shake.trauma - TRAUMA_DECAY * time.delta_seconds()).max(0.0);
+		if shake.trauma == 0.0 || settings.reduce_motion {
+			continue;
+		}
+
+		let amount = shake.trauma * shake.trauma;
+		let t = time.seconds_since_startup() as f32 * SHAKE_FREQUENCY;
+		// Sums of incommensurate sines are a cheap stand-in for smooth noise.
+		let noise = |phase: f32| ((t + phase).sin() + (t * 1.7 + phase * 3.1).sin()) / 2.0;
+
+		shake.offset = Vec2::new(noise(0.0), noise(10.0))
+			* MAX_SHAKE_OFFSET
+			* amount * camera_transform.scale.xy();
+		camera_transform.translation.x += shake.offset.x;
+		camera_transform.translation.y += shake.offset.y;
+		camera_transform.rotation = Quat::from_rotation_z(MAX_SHAKE_ANGLE * amount * noise(20.0));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bevy::app::Events;
+	use bevy::render::camera::{camera_system, CameraProjection, OrthographicProjection};
+	use bevy::window::{WindowCreated, WindowDescriptor, WindowId, WindowResized};
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	/// Logical window sizes and scale factors, including a resize to the smallest window.
+	const WINDOWS: [(f32, f32, f64); 4] = [
+		(1280.0, 720.0, 1.0),
+		(1920.0, 1080.0, 2.0),
+		(
+			crate::cli::MIN_WINDOW_SIZE.0,
+			crate::cli::MIN_WINDOW_SIZE.1,
+			1.0,
+		),
+		(720.0, 1280.0, 1.5),
+	];
+	const SCALES: [f32; 4] = [MIN_ZOOM, 0.3, 0.55, MAX_ZOOM];
+
+	fn window(width: f32, height: f32, scale_factor: f64) -> Window {
+		Window::new(
+			WindowId::primary(),
+			&WindowDescriptor {
+				width,
+				height,
+				..Default::default()
+			},
+			(f64::from(width) * scale_factor) as u32,
+			(f64::from(height) * scale_factor) as u32,
+			scale_factor,
+			None,
+		)
+	}
+
+	/// The camera as the 2d camera bundle sets it up once the window has its size.
+	fn camera(window: &Window, position: Vec2, scale: f32) -> (Camera, GlobalTransform) {
+		let mut projection = OrthographicProjection::default();
+		projection.update(window.width(), window.height());
+		let camera = Camera {
+			projection_matrix: projection.get_projection_matrix(),
+			..Default::default()
+		};
+		let camera_transform = GlobalTransform {
+			translation: position.extend(999.9),
+			rotation: Quat::IDENTITY,
+			scale: Vec3::new(scale, scale, 1.0),
+		};
+		(camera, camera_transform)
+	}
+
+	fn assert_close(actual: Vec2, expected: Vec2) {
+		assert!(
+			(actual - expected).abs().max_element() < 1e-2,
+			"expected {}, got {}",
+			expected,
+			actual
+		);
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn screen_and_world_positions_round_trip() {
+		for &(width, height, scale_factor) in WINDOWS.iter() {
+			let window = window(width, height, scale_factor);
+			for &scale in SCALES.iter() {
+				let (camera, camera_transform) = camera(&window, Vec2::new(150.0, -40.0), scale);
+				for &position in [
+					Vec2::ZERO,
+					Vec2::new(150.0, -40.0),
+					Vec2::new(-321.5, 87.25),
+					Vec2::new(1000.0, 1000.0),
+				]
+				.iter()
+				{
+					let screen = world_to_screen(&window, &camera, &camera_transform, position);
+					let world = screen_to_world(&window, &camera, &camera_transform, screen);
+					assert_close(world, position);
+				}
+			}
+		}
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn window_corners_map_to_the_view_corners() {
+		for &(width, height, scale_factor) in WINDOWS.iter() {
+			let window = window(width, height, scale_factor);
+			for &scale in SCALES.iter() {
+				let center = Vec2::new(-64.0, 200.0);
+				let (camera, camera_transform) = camera(&window, center, scale);
+				let half_view = Vec2::new(width, height) / 2.0 * scale;
+				let to_world =
+					|position: Vec2| screen_to_world(&window, &camera, &camera_transform, position);
+				assert_close(to_world(Vec2::ZERO), center - half_view);
+				assert_close(to_world(Vec2::new(width, height)), center + half_view);
+				assert_close(
+					to_world(Vec2::new(0.0, height)),
+					center + Vec2::new(-half_view.x, half_view.y),
+				);
+				assert_close(to_world(Vec2::new(width, height) / 2.0), center);
+			}
+		}
+	}
+
+	/// The systems in the stages the app runs them in: the cursor is read before the projections
+	/// are updated to the window size at the end of the frame.
+	fn schedule() -> Schedule {
+		Schedule::default()
+			.with_stage(
+				CoreStage::PreUpdate,
+				SystemStage::single_threaded().with_system(update_cursor_world_position.system()),
+			)
+			.with_stage(
+				CoreStage::PostUpdate,
+				SystemStage::single_threaded()
+					.with_system(camera_system::<OrthographicProjection>.system()),
+			)
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn cursor_world_position_follows_resizes_and_zoom() {
+		let (width, height, scale_factor) = WINDOWS[0];
+		let mut windows = Windows::default();
+		windows.add(window(width, height, scale_factor));
+		let mut world = World::default();
+		world.insert_resource(windows);
+		world.insert_resource(Events::<WindowResized>::default());
+		world.insert_resource(Events::<WindowCreated>::default());
+		world.init_resource::<CursorWorldPosition>();
+		// Only the main camera counts, not the UI camera.
+		world.spawn().insert_bundle((
+			Camera::default(),
+			OrthographicProjection::default(),
+			GlobalTransform::from_translation(Vec3::new(-500.0, 300.0, 0.0)),
+		));
+		let center = Vec2::new(150.0, -40.0);
+		let projection_scale = 2.0;
+		let camera = world
+			.spawn()
+			.insert_bundle((
+				MainCamera,
+				Camera::default(),
+				OrthographicProjection {
+					scale: projection_scale,
+					..Default::default()
+				},
+				GlobalTransform::from_translation(center.extend(999.9)),
+			))
+			.id();
+		let mut schedule = schedule();
+		let cursor_world_position =
+			|world: &World| world.get_resource::<CursorWorldPosition>().unwrap().0;
+
+		for &(width, height, scale_factor) in WINDOWS.iter() {
+			let cursor = Vec2::new(width * 0.25, height * 0.75);
+			let mut windows = world.get_resource_mut::<Windows>().unwrap();
+			let window = windows.get_primary_mut().unwrap();
+			window.update_scale_factor_from_backend(scale_factor);
+			window.update_actual_size_from_backend(
+				(f64::from(width) * scale_factor) as u32,
+				(f64::from(height) * scale_factor) as u32,
+			);
+			window.update_cursor_position_from_backend(Some(cursor));
+			world
+				.get_resource_mut::<Events<WindowResized>>()
+				.unwrap()
+				.send(WindowResized {
+					id: WindowId::primary(),
+					width,
+					height,
+				});
+			// The projection catches up with the new size at the end of the frame.
+			schedule.run(&mut world);
+
+			for &scale in SCALES.iter() {
+				world.get_mut::<GlobalTransform>(camera).unwrap().scale =
+					Vec3::new(scale, scale, 1.0);
+				schedule.run(&mut world);
+				let offset = cursor - Vec2::new(width, height) / 2.0;
+				assert_close(
+					cursor_world_position(&world).unwrap(),
+					center + offset * projection_scale * scale,
+				);
+			}
+		}
+
+		let mut windows = world.get_resource_mut::<Windows>().unwrap();
+		windows
+			.get_primary_mut()
+			.unwrap()
+			.update_cursor_position_from_backend(None);
+		schedule.run(&mut world);
+		assert_eq!(cursor_world_position(&world), None);
+	}
+}
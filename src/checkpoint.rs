@@ -0,0 +1,144 @@
+use crate::components::*;
+use crate::console::ConsoleEvent;
+use crate::level::ENTITY_Z;
+use crate::AppState;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+
+/// How long the respawn animation takes, in seconds.
+const RESPAWN_DURATION: f32 = 0.4;
+/// How long the player can't be hurt after respawning, in seconds.
+const INVULNERABILITY_DURATION: f32 = 1.5;
+/// How often the player blinks while invulnerable, in seconds.
+const BLINK_INTERVAL: f32 = 0.1;
+
+pub struct CheckpointPlugin;
+
+impl Plugin for CheckpointPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<RespawnPoint>().add_system_set(
+			SystemSet::on_update(AppState::Game)
+				.with_system(detect_checkpoint_collision.system())
+				.with_system(animate_respawn.system())
+				.with_system(update_invulnerability.system()),
+		);
+	}
+}
+
+/// Where the player comes back after getting hurt.
+#[derive(Default)]
+pub struct RespawnPoint(pub Vec2);
+
+pub fn spawn_checkpoint(
+	commands: &mut Commands,
+	materials: &Textures,
+	position: Vec2,
+	active: bool,
+) -> Entity {
+	commands
+		.spawn_bundle(SpriteBundle {
+			material: if active {
+				materials.checkpoint_active_texture.clone()
+			} else {
+				materials.checkpoint_texture.clone()
+			},
+			// The tree texture is much bigger than a tile.
+			sprite: Sprite::new(Vec2::splat(TILE_SIZE)),
+			transform: Transform {
+				translation: position.extend(ENTITY_Z),
+				..Default::default()
+			},
+			..Default::default()
+		})
+		.insert(Checkpoint)
+		.id()
+}
+
+/// Moves the player to the last activated checkpoint and plays the respawn animation.
+pub fn respawn_player(
+	commands: &mut Commands,
+	respawn_point: &RespawnPoint,
+	player: Entity,
+	player_transform: &mut Transform,
+) {
+	player_transform.translation.x = respawn_point.0.x;
+	player_transform.translation.y = respawn_point.0.y;
+	player_transform.scale = Vec3::ZERO;
+	commands
+		.entity(player)
+		.insert(Respawning(Timer::from_seconds(RESPAWN_DURATION, false)));
+}
+
+fn detect_checkpoint_collision(
+	materials: Res<Textures>,
+	mut respawn_point: ResMut<RespawnPoint>,
+	mut console_events: EventWriter<ConsoleEvent>,
+	players: Query<(&Transform, &Hitbox), (With<Player>, Changed<Transform>)>,
+	mut checkpoints: Query<(&Transform, &Sprite, &mut Handle<ColorMaterial>), With<Checkpoint>>,
+) {
+	for (player, Hitbox(player_size)) in players.iter() {
+		let touched = checkpoints
+			.iter_mut()
+			.find(|(checkpoint, checkpoint_sprite, _)| {
+				(player.translation.x - checkpoint.translation.x).abs() * 2.0
+					< (player_size.x + checkpoint_sprite.size.x)
+					&& (player.translation.y - checkpoint.translation.y).abs() * 2.0
+						< (player_size.y + checkpoint_sprite.size.y)
+			});
+		let position = match touched {
+			Some((checkpoint, _, _)) => checkpoint.translation.xy(),
+			None => continue,
+		};
+		if position == respawn_point.0 {
+			continue;
+		}
+
+		info!("Checkpoint activated at {:?}", position);
+		console_events.send(ConsoleEvent::from("Checkpoint!\n"));
+		respawn_point.0 = position;
+		for (checkpoint, _, mut material) in checkpoints.iter_mut() {
+			*material = if checkpoint.translation.xy() == position {
+				materials.checkpoint_active_texture.clone()
+			} else {
+				materials.checkpoint_texture.clone()
+			};
+		}
+	}
+}
+
+fn animate_respawn(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut players: Query<(Entity, &mut Transform, &mut Respawning)>,
+) {
+	for (player, mut transform, mut respawning) in players.iter_mut() {
+		let progress = respawning.0.tick(time.delta()).percent();
+		transform.scale = Vec3::new(progress, progress, 1.0);
+		if respawning.0.finished() {
+			transform.scale = Vec3::ONE;
+			commands
+				.entity(player)
+				.remove::<Respawning>()
+				.insert(Invulnerable(Timer::from_seconds(
+					INVULNERABILITY_DURATION,
+					false,
+				)));
+		}
+	}
+}
+
+fn update_invulnerability(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut players: Query<(Entity, &mut Visible, &mut Invulnerable)>,
+) {
+	for (player, mut visible, mut invulnerable) in players.iter_mut() {
+		invulnerable.0.tick(time.delta());
+		if invulnerable.0.finished() {
+			visible.is_visible = true;
+			commands.entity(player).remove::<Invulnerable>();
+		} else {
+			visible.is_visible = (invulnerable.0.elapsed_secs() / BLINK_INTERVAL) as u32 % 2 == 0;
+		}
+	}
+}
@@ -0,0 +1,402 @@
+use crate::components::LevelType;
+use crate::i18n::Language;
+use bevy::log::Level;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+pub static USAGE: &str = "\
+Usage: kod_jam [OPTIONS]
+       kod_jam stats [FILE]
+
+Commands:
+    stats [FILE]          Summarize the sessions saved with --stats (default: stats.jsonl)
+
+Options:
+    --level <LEVEL>       Start directly in the given level (Hub, Secret1, Level1)
+    --skip-menu           Start the game without showing the main menu
+    --editor              Start in the level editor
+    --window <WxH>        Window size in logical pixels, e.g. 1280x720
+    --fullscreen          Start in borderless fullscreen
+    --seed <SEED>         Seed for everything random in the game
+    --log-level <LEVEL>   One of trace, debug, info, warn, error
+    --headless            Run without a window or a renderer
+    --reduce-motion       Disable camera shake
+    --coop                Add a second player on a gamepad or the arrow keys
+    --friendly-fire       Let missiles hurt the other player
+    --host <PORT>         Host a networked game on the given UDP port
+    --connect <ADDR>      Join a networked game, e.g. 127.0.0.1:7777
+    --stats               Append the stats of this session to stats.jsonl on exit
+    --language <CODE>     Language of the game text (en, pl)
+    --replay <FILE>       Play back an input recording
+    --record <FILE>       Record input to a file
+    --config <FILE>       Read options from a file, one `option = value` per line
+    -h, --help            Print this help";
+
+/// Smallest window size that fits the window resize constraints.
+pub const MIN_WINDOW_SIZE: (f32, f32) = (1280.0 / 4.0, 720.0 / 4.0);
+
+/// Parsed command line, available to plugins as a resource.
+#[derive(Debug, Default, Clone)]
+pub struct Args {
+	pub level: Option<LevelType>,
+	pub skip_menu: bool,
+	pub editor: bool,
+	pub window: Option<(f32, f32)>,
+	pub fullscreen: bool,
+	pub seed: Option<u64>,
+	pub log_level: Option<Level>,
+	pub headless: bool,
+	pub reduce_motion: bool,
+	pub coop: bool,
+	pub friendly_fire: bool,
+	pub host: Option<u16>,
+	pub connect: Option<SocketAddr>,
+	pub stats: bool,
+	pub language: Option<Language>,
+	pub replay: Option<PathBuf>,
+	pub record: Option<PathBuf>,
+	pub config: Option<PathBuf>,
+	pub help: bool,
+	/// Runs instead of the game when given.
+	pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+	/// Prints the totals of the stats file.
+	Stats(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum ArgsError {
+	UnknownOption(String),
+	MissingValue(&'static str),
+	InvalidValue {
+		option: &'static str,
+		value: String,
+		reason: String,
+	},
+	Config {
+		path: PathBuf,
+		line: usize,
+		error: Box<ArgsError>,
+	},
+	ConfigIo(PathBuf, std::io::Error),
+}
+
+impl fmt::Display for ArgsError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ArgsError::UnknownOption(option) => write!(f, "unknown option {:?}", option),
+			ArgsError::MissingValue(option) => write!(f, "{} requires a value", option),
+			ArgsError::InvalidValue {
+				option,
+				value,
+				reason,
+			} => write!(f, "invalid value {:?} for {}: {}", value, option, reason),
+			ArgsError::Config { path, line, error } => {
+				write!(f, "{}:{}: {}", path.display(), line, error)
+			}
+			ArgsError::ConfigIo(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+		}
+	}
+}
+
+impl std::error::Error for ArgsError {}
+
+impl Args {
+	/// Parses the process arguments. Prints the usage and exits on `--help` or invalid input.
+	pub fn from_env() -> Self {
+		match Args::parse(std::env::args().skip(1)) {
+			Ok(args) if args.help => {
+				println!("{}", USAGE);
+				std::process::exit(0);
+			}
+			Ok(args) => args,
+			Err(e) => {
+				eprintln!("error: {}\n\n{}", e, USAGE);
+				std::process::exit(2);
+			}
+		}
+	}
+
+	/// Parses the arguments (without the program name). Options given on the command line take
+	/// precedence over the ones read from `--config`.
+	pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+		let mut args = args.into_iter().peekable();
+		let mut parsed = Args::default();
+		if args.peek().map(String::as_str) == Some("stats") {
+			args.next();
+			let path = args
+				.next()
+				.map_or_else(|| PathBuf::from(crate::stats::STATS_PATH), PathBuf::from);
+			if let Some(arg) = args.next() {
+				return Err(ArgsError::UnknownOption(arg));
+			}
+			parsed.command = Some(Command::Stats(path));
+			return Ok(parsed);
+		}
+		parsed.apply(args)?;
+
+		if let Some(path) = parsed.config.clone() {
+			let mut from_config = Args::default();
+			from_config.apply_config(&path)?;
+			parsed = from_config.overridden_by(parsed);
+		}
+		if let (Some(_), Some(address)) = (parsed.host, parsed.connect) {
+			return Err(ArgsError::InvalidValue {
+				option: "--connect",
+				value: address.to_string(),
+				reason: "can't be combined with --host".to_string(),
+			});
+		}
+
+		Ok(parsed)
+	}
+
+	fn apply(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), ArgsError> {
+		let mut args = args.into_iter();
+		while let Some(arg) = args.next() {
+			let mut value =
+				|option: &'static str| args.next().ok_or(ArgsError::MissingValue(option));
+			match arg.as_str() {
+				"--level" => self.level = Some(parse_value("--level", value("--level")?)?),
+				"--skip-menu" => self.skip_menu = true,
+				"--editor" => self.editor = true,
+				"--window" => self.window = Some(parse_window_size(value("--window")?)?),
+				"--fullscreen" => self.fullscreen = true,
+				"--seed" => self.seed = Some(parse_value("--seed", value("--seed")?)?),
+				"--log-level" => {
+					self.log_level = Some(parse_value("--log-level", value("--log-level")?)?)
+				}
+				"--headless" => self.headless = true,
+				"--reduce-motion" => self.reduce_motion = true,
+				"--coop" => self.coop = true,
+				"--friendly-fire" => self.friendly_fire = true,
+				"--host" => self.host = Some(parse_value("--host", value("--host")?)?),
+				"--connect" => self.connect = Some(parse_value("--connect", value("--connect")?)?),
+				"--stats" => self.stats = true,
+				"--language" => {
+					self.language = Some(parse_value("--language", value("--language")?)?)
+				}
+				"--replay" => self.replay = Some(value("--replay")?.into()),
+				"--record" => self.record = Some(value("--record")?.into()),
+				"--config" => self.config = Some(value("--config")?.into()),
+				"-h" | "--help" => self.help = true,
+				_ => return Err(ArgsError::UnknownOption(arg)),
+			}
+		}
+		Ok(())
+	}
+
+	fn apply_config(&mut self, path: &Path) -> Result<(), ArgsError> {
+		let contents = std::fs::read_to_string(path)
+			.map_err(|e| ArgsError::ConfigIo(path.to_path_buf(), e))?;
+		for (idx, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let mut args = Vec::with_capacity(2);
+			match line.split_once('=') {
+				Some((option, value)) => {
+					args.push(format!("--{}", option.trim()));
+					args.push(value.trim().to_string());
+				}
+				None => args.push(format!("--{}", line)),
+			}
+			self.apply(args).map_err(|e| ArgsError::Config {
+				path: path.to_path_buf(),
+				line: idx + 1,
+				error: Box::new(e),
+			})?;
+		}
+		Ok(())
+	}
+
+	fn overridden_by(self, other: Args) -> Args {
+		Args {
+			level: other.level.or(self.level),
+			skip_menu: other.skip_menu || self.skip_menu,
+			editor: other.editor || self.editor,
+			window: other.window.or(self.window),
+			fullscreen: other.fullscreen || self.fullscreen,
+			seed: other.seed.or(self.seed),
+			log_level: other.log_level.or(self.log_level),
+			headless: other.headless || self.headless,
+			reduce_motion: other.reduce_motion || self.reduce_motion,
+			coop: other.coop || self.coop,
+			friendly_fire: other.friendly_fire || self.friendly_fire,
+			host: other.host.or(self.host),
+			connect: other.connect.or(self.connect),
+			stats: other.stats || self.stats,
+			language: other.language.or(self.language),
+			replay: other.replay.or(self.replay),
+			record: other.record.or(self.record),
+			config: other.config,
+			help: other.help || self.help,
+			command: other.command.or(self.command),
+		}
+	}
+}
+
+fn parse_value<T>(option: &'static str, value: String) -> Result<T, ArgsError>
+where
+	T: std::str::FromStr,
+	T::Err: fmt::Display,
+{
+	value.parse().map_err(|e: T::Err| ArgsError::InvalidValue {
+		option,
+		reason: e.to_string(),
+		value,
+	})
+}
+
+fn parse_window_size(value: String) -> Result<(f32, f32), ArgsError> {
+	let invalid = |reason: String| ArgsError::InvalidValue {
+		option: "--window",
+		value: value.clone(),
+		reason,
+	};
+	let (width, height) = value
+		.split_once('x')
+		.ok_or_else(|| invalid("expected WIDTHxHEIGHT".to_string()))?;
+	let width = width
+		.parse::<u32>()
+		.map_err(|e| invalid(format!("width: {}", e)))? as f32;
+	let height = height
+		.parse::<u32>()
+		.map_err(|e| invalid(format!("height: {}", e)))? as f32;
+	if width < MIN_WINDOW_SIZE.0 || height < MIN_WINDOW_SIZE.1 {
+		return Err(invalid(format!(
+			"must be at least {}x{}",
+			MIN_WINDOW_SIZE.0, MIN_WINDOW_SIZE.1
+		)));
+	}
+	Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+		Args::parse(args.iter().map(|arg| arg.to_string()))
+	}
+
+	/// Writes `contents` to a config file unique to the test.
+	fn config_file(name: &str, contents: &str) -> PathBuf {
+		let path =
+			std::env::temp_dir().join(format!("kod_jam_{}_{}.cfg", std::process::id(), name));
+		std::fs::write(&path, contents).unwrap();
+		path
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn options_are_parsed() {
+		let args = parse(&[
+			"--level",
+			"Level1",
+			"--window",
+			"1280x720",
+			"--seed",
+			"42",
+			"--log-level",
+			"debug",
+			"--language",
+			"pl",
+			"--coop",
+			"--record",
+			"out.rec",
+		])
+		.unwrap();
+		assert_eq!(args.level, Some(LevelType::Level1));
+		assert_eq!(args.window, Some((1280.0, 720.0)));
+		assert_eq!(args.seed, Some(42));
+		assert_eq!(args.log_level, Some(Level::DEBUG));
+		assert_eq!(args.language, Some(Language::Polish));
+		assert!(args.coop);
+		assert_eq!(args.record, Some(PathBuf::from("out.rec")));
+		assert!(!args.help);
+		assert!(parse(&["-h"]).unwrap().help);
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn invalid_input_is_rejected() {
+		assert!(matches!(
+			parse(&["--bogus"]),
+			Err(ArgsError::UnknownOption(option)) if option == "--bogus"
+		));
+		assert!(matches!(
+			parse(&["--seed"]),
+			Err(ArgsError::MissingValue("--seed"))
+		));
+		let invalid: [&[&str]; 7] = [
+			&["--level", "Level9"],
+			&["--seed", "-1"],
+			&["--window", "1280"],
+			&["--window", "10x10"],
+			&["--log-level", "loud"],
+			&["--language", "de"],
+			&["--host", "7777", "--connect", "127.0.0.1:7777"],
+		];
+		for args in invalid.iter() {
+			assert!(
+				matches!(parse(args), Err(ArgsError::InvalidValue { .. })),
+				"{:?} was accepted",
+				args
+			);
+		}
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn stats_command_takes_an_optional_file() {
+		assert!(matches!(
+			parse(&["stats"]).unwrap().command,
+			Some(Command::Stats(path)) if path == Path::new(crate::stats::STATS_PATH)
+		));
+		assert!(matches!(
+			parse(&["stats", "old.jsonl"]).unwrap().command,
+			Some(Command::Stats(path)) if path == Path::new("old.jsonl")
+		));
+		assert!(parse(&["stats", "a", "b"]).is_err());
+	}
+
+	#[test]
+	fn command_line_takes_precedence_over_config() {
+		let path = config_file(
+			"precedence",
+			"# Comment\n\nlevel = Hub\nseed = 1\nreduce-motion\n",
+		);
+		let args = parse(&["--config", path.to_str().unwrap(), "--seed", "2"]).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(args.level, Some(LevelType::Hub));
+		assert_eq!(args.seed, Some(2));
+		assert!(args.reduce_motion);
+		assert_eq!(args.config, Some(path));
+	}
+
+	#[test]
+	fn config_errors_name_the_line() {
+		let path = config_file("error", "seed = 1\nwindow = big\n");
+		let result = parse(&["--config", path.to_str().unwrap()]);
+		std::fs::remove_file(&path).unwrap();
+		match result {
+			Err(ArgsError::Config { line, error, .. }) => {
+				assert_eq!(line, 2);
+				assert!(matches!(*error, ArgsError::InvalidValue { .. }));
+			}
+			result => panic!("expected a config error, got {:?}", result),
+		}
+		assert!(matches!(
+			parse(&["--config", "/nonexistent/kod_jam.cfg"]),
+			Err(ArgsError::ConfigIo(..))
+		));
+	}
+}
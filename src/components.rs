@@ -1,23 +1,98 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
 
 pub const TILE_SIZE: f32 = 32.0;
 
 pub struct Textures {
-	pub player_texture: Handle<ColorMaterial>,
 	pub ground_tile: Handle<ColorMaterial>,
 	pub transparent_ground_tile: Handle<ColorMaterial>,
 	pub portal_texture: Handle<ColorMaterial>,
 	pub spikes_texture: Handle<ColorMaterial>,
 	pub missile_texture: Handle<ColorMaterial>,
+	pub checkpoint_texture: Handle<ColorMaterial>,
+	pub checkpoint_active_texture: Handle<ColorMaterial>,
+	pub water_tile: Handle<ColorMaterial>,
+	pub bridge_tile: Handle<ColorMaterial>,
+	pub conveyor_tile: Handle<ColorMaterial>,
+	pub enemy_texture: Handle<ColorMaterial>,
 }
 
 pub struct MainCamera;
 
-pub struct Player;
+/// Index of the player: 0 for the first one, 1 for the second one in local co-op.
+pub struct Player(pub usize);
+
+/// How many players can play at once.
+pub const MAX_PLAYERS: usize = 2;
+
+/// Direction a player without the crosshair fires in.
+pub struct AimDirection(pub Vec2);
+
+/// Size of the player used for collisions. Matches `saitama_fit.png`, not the sprite sheet frame.
+pub const PLAYER_SIZE: (f32, f32) = (21.0, 32.0);
+
+/// Axis aligned size of an entity used for collisions, for entities without a [`Sprite`].
+pub struct Hitbox(pub Vec2);
+
+/// Direction the player wants to move in, as pressed on the input devices.
+pub struct MoveDirection(pub Vec2);
+
+/// Hit points of the player. The game is over when they run out.
+pub struct Health {
+	pub current: u32,
+	pub max: u32,
+}
+
+impl Health {
+	pub fn new(max: u32) -> Self {
+		Health { current: max, max }
+	}
+}
+
+/// The weapon the player is holding.
+pub struct Weapon {
+	/// Translation key of the weapon name.
+	pub name: &'static str,
+	/// Seconds between two shots.
+	pub cooldown: f32,
+	/// Seconds left until the next shot.
+	pub cooldown_left: f32,
+	/// How far the projectile flies, or `None` if it flies until it hits something.
+	pub range: Option<f32>,
+}
+
+impl Weapon {
+	pub fn missile_launcher() -> Self {
+		Weapon {
+			name: "weapon-missile-launcher",
+			cooldown: 0.3,
+			cooldown_left: 0.0,
+			range: None,
+		}
+	}
+}
+
+/// Points collected in the current run.
+#[derive(Debug, Default)]
+pub struct Score(pub u32);
 
 pub struct Spikes;
 
+/// Hurts the player on touch like [`Spikes`], but can be shot down.
+pub struct Enemy;
+
+pub struct Checkpoint;
+
+/// The player is playing the respawn animation and can't move.
+pub struct Respawning(pub Timer);
+
+/// The player can't be hurt until the timer finishes.
+pub struct Invulnerable(pub Timer);
+
 pub struct Missile {
+	/// The player who fired it. Missiles never hurt their owner.
+	pub owner: Entity,
 	pub direction: Vec3,
 	pub speed: Vec3,
 }
@@ -38,18 +113,95 @@ impl Default for Level {
 	}
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum LevelType {
 	Hub,
 	Secret1,
 	Level1,
 }
 
+impl LevelType {
+	pub const ALL: [LevelType; 3] = [LevelType::Hub, LevelType::Secret1, LevelType::Level1];
+
+	/// Path of the level file, relative to the assets directory, or `None` for generated levels.
+	pub fn file_name(self) -> Option<&'static str> {
+		match self {
+			LevelType::Hub => Some("levels/hub.level"),
+			LevelType::Secret1 => None,
+			LevelType::Level1 => Some("levels/level1.level"),
+		}
+	}
+
+	/// Translation key of the level name shown to the player.
+	pub fn name_key(self) -> &'static str {
+		match self {
+			LevelType::Hub => "level-hub",
+			LevelType::Secret1 => "level-secret1",
+			LevelType::Level1 => "level-level1",
+		}
+	}
+}
+
+impl std::str::FromStr for LevelType {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"Hub" => Ok(LevelType::Hub),
+			"Secret1" => Ok(LevelType::Secret1),
+			"Level1" => Ok(LevelType::Level1),
+			_ => Err("expected one of Hub, Secret1, Level1".to_string()),
+		}
+	}
+}
+
+impl std::fmt::Display for LevelType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			LevelType::Hub => "Hub",
+			LevelType::Secret1 => "Secret 1",
+			LevelType::Level1 => "Level 1",
+		};
+		f.write_str(name)
+	}
+}
+
 impl Level {
 	pub fn hub() -> Self {
 		Level {
-			size: (15.0 * TILE_SIZE, 10.0 * TILE_SIZE).into(),
+			size: (14.0 * TILE_SIZE, 10.0 * TILE_SIZE).into(),
 			l_type: LevelType::Hub,
 		}
 	}
+
+	/// Horizontal tile coordinates of the level floor, without the surrounding wall.
+	pub fn tile_columns(&self) -> Range<i32> {
+		let columns = (self.size.x / TILE_SIZE).round() as i32;
+		-columns / 2..columns - columns / 2
+	}
+
+	/// Vertical tile coordinates of the level floor, without the surrounding wall.
+	pub fn tile_rows(&self) -> Range<i32> {
+		let rows = (self.size.y / TILE_SIZE).round() as i32;
+		-rows / 2..rows - rows / 2
+	}
+
+	/// Bottom left and top right corners of the area covered by the level tiles, including the
+	/// surrounding wall.
+	pub fn bounds(&self) -> (Vec2, Vec2) {
+		let (columns, rows) = (self.tile_columns(), self.tile_rows());
+		let first = Vec2::new((columns.start - 1) as f32, (rows.start - 1) as f32);
+		let last = Vec2::new(columns.end as f32, rows.end as f32);
+		(
+			first * TILE_SIZE - Vec2::splat(TILE_SIZE / 2.0),
+			last * TILE_SIZE + Vec2::splat(TILE_SIZE / 2.0),
+		)
+	}
+
+	pub fn new(l_type: LevelType) -> Self {
+		Level {
+			l_type,
+			..Level::hub()
+		}
+	}
 }
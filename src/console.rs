@@ -1,3 +1,4 @@
+use crate::i18n::Locale;
 use bevy::app::Events;
 use bevy::prelude::*;
 
@@ -6,8 +7,10 @@ pub struct ConsolePlugin;
 impl Plugin for ConsolePlugin {
 	fn build(&self, app: &mut AppBuilder) {
 		app.add_event::<ConsoleEvent>()
+			.add_event::<ConsoleCommand>()
 			.add_startup_system(setup_console.system())
 			.add_system(console_trigger.system())
+			.add_system(console_input.system())
 			.add_system(add_to_console.system())
 			.add_system(process_console_events.system())
 			.add_system(update_console_ui.system());
@@ -18,6 +21,13 @@ pub struct ConsoleComponent;
 
 pub struct ConsoleBuffer(String);
 
+/// Command line typed into the open console.
+pub struct ConsoleInput(String);
+
+/// A line entered into the console. Plugins react to the commands they know.
+#[derive(Debug)]
+pub struct ConsoleCommand(pub String);
+
 fn setup_console(mut commands: Commands, asset_server: Res<AssetServer>) {
 	let font = asset_server.load("FiraMono-Medium.ttf");
 
@@ -26,9 +36,6 @@ fn setup_console(mut commands: Commands, asset_server: Res<AssetServer>) {
 		is_transparent: true,
 	};
 
-	commands
-		.spawn_bundle(UiCameraBundle::default())
-		.insert(ConsoleComponent);
 	commands
 		.spawn_bundle(NodeBundle {
 			style: Style {
@@ -75,7 +82,8 @@ fn setup_console(mut commands: Commands, asset_server: Res<AssetServer>) {
 					},
 					..Default::default()
 				})
-				.insert(ConsoleBuffer("Console\n".to_string()))
+				.insert(ConsoleBuffer(String::new()))
+				.insert(ConsoleInput(String::new()))
 				.insert(ConsoleComponent);
 		});
 }
@@ -99,10 +107,59 @@ fn console_trigger(
 	}
 }
 
-fn update_console_ui(mut q: Query<(&mut Text, &ConsoleBuffer), Changed<ConsoleBuffer>>) {
-	for (mut text, console_buffer) in q.iter_mut() {
+fn console_input(
+	kb_input: Res<Input<KeyCode>>,
+	mut characters: EventReader<ReceivedCharacter>,
+	mut commands: EventWriter<ConsoleCommand>,
+	mut console_events: EventWriter<ConsoleEvent>,
+	mut q: Query<(&mut ConsoleInput, &Visible)>,
+) {
+	// The grave accent comes from the key that toggles the console.
+	let typed = characters
+		.iter()
+		.map(|character| character.char)
+		.filter(|c| !c.is_control() && *c != '`')
+		.collect::<String>();
+	for (mut input, visible) in q.iter_mut() {
+		if !visible.is_visible {
+			continue;
+		}
+		if !typed.is_empty() {
+			input.0.push_str(&typed);
+		}
+		if kb_input.just_pressed(KeyCode::Back) {
+			input.0.pop();
+		}
+		if kb_input.just_pressed(KeyCode::Return) && !input.0.trim().is_empty() {
+			let command = std::mem::take(&mut input.0);
+			console_events.send(ConsoleEvent::Log(format!("> {}\n", command)));
+			commands.send(ConsoleCommand(command.trim().to_string()));
+		}
+	}
+}
+
+#[allow(clippy::type_complexity)]
+fn update_console_ui(
+	locale: Res<Locale>,
+	mut q: Query<(
+		&mut Text,
+		&ConsoleBuffer,
+		&ConsoleInput,
+		ChangeTrackers<ConsoleBuffer>,
+		ChangeTrackers<ConsoleInput>,
+	)>,
+) {
+	for (mut text, console_buffer, console_input, buffer_trackers, input_trackers) in q.iter_mut() {
+		if !locale.is_changed() && !buffer_trackers.is_changed() && !input_trackers.is_changed() {
+			continue;
+		}
 		if let Some(text_section) = text.sections.iter_mut().next() {
-			text_section.value = console_buffer.0.clone()
+			text_section.value = format!(
+				"{}\n{}> {}",
+				locale.get("console-title"),
+				console_buffer.0,
+				console_input.0
+			);
 		}
 	}
 }
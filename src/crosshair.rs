@@ -0,0 +1,270 @@
+use crate::camera::{world_to_screen, CursorWorldPosition};
+use crate::components::*;
+use crate::replay::{Controls, PlayerControls};
+use crate::ui::ActiveStateScoped;
+use crate::AppState;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::render::camera::Camera;
+use bevy::window::CursorMoved;
+
+/// Size of the crosshair on the screen, in pixels. Keeps the aspect ratio of the texture.
+const CROSSHAIR_SIZE: (f32, f32) = (40.0, 30.0);
+/// Crosshair scale while the weapon has just fired. It grows back to 1 as the weapon cools down.
+const COOLING_CROSSHAIR_SCALE: f32 = 0.6;
+/// How far from the player the crosshair sits when aiming with a gamepad.
+pub const GAMEPAD_AIM_DISTANCE: f32 = 4.0 * TILE_SIZE;
+/// Stick deflection below which the gamepad aim is ignored.
+pub const GAMEPAD_AIM_DEAD_ZONE: f32 = 0.3;
+const RANGE_LINE_WIDTH: f32 = 1.0;
+
+pub struct CrosshairPlugin;
+
+impl Plugin for CrosshairPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<Aim>()
+			.init_resource::<CrosshairMaterials>()
+			.add_system_set(
+				SystemSet::on_enter(AppState::Game).with_system(setup_crosshair.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(update_aim.system().label(AimLabel))
+					.with_system(update_crosshair.system().after(AimLabel))
+					.with_system(update_range_line.system().after(AimLabel)),
+			)
+			.add_system_set(SystemSet::on_exit(AppState::Game).with_system(show_cursor.system()))
+			.add_system_set(SystemSet::on_pause(AppState::Game).with_system(show_cursor.system()))
+			.add_system_set(
+				SystemSet::on_resume(AppState::Game).with_system(setup_crosshair.system()),
+			);
+	}
+}
+
+/// Systems reading [`Aim`] have to run after this label.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AimLabel;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AimSource {
+	Mouse,
+	Gamepad,
+}
+
+/// Where the first player is aiming, following whichever of the mouse and the gamepad was used
+/// last. Other players aim with [`AimDirection`].
+pub struct Aim {
+	/// Aim position in the world coordinates, `None` if the cursor is outside of the window.
+	pub position: Option<Vec2>,
+	source: AimSource,
+	/// Last direction the gamepad stick pointed to.
+	gamepad_direction: Vec2,
+}
+
+impl Default for Aim {
+	fn default() -> Self {
+		Aim {
+			position: None,
+			source: AimSource::Mouse,
+			gamepad_direction: Vec2::X,
+		}
+	}
+}
+
+struct CrosshairMaterials {
+	ready: Handle<ColorMaterial>,
+	cooling: Handle<ColorMaterial>,
+	range_line: Handle<ColorMaterial>,
+}
+
+impl FromWorld for CrosshairMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let texture = world.get_resource::<AssetServer>().unwrap().load(
+			"LowPoly_Missile_command_Game_Assets_DevilsGarage_v01/2D/bg_resequ_crosshair.png",
+		);
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		CrosshairMaterials {
+			ready: materials.add(ColorMaterial::texture(texture.clone())),
+			cooling: materials.add(ColorMaterial::modulated_texture(
+				texture,
+				Color::rgba(1.0, 0.4, 0.4, 0.6),
+			)),
+			range_line: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.4).into()),
+		}
+	}
+}
+
+struct Crosshair;
+
+/// Line from the player toward the crosshair, as long as the weapon range.
+struct RangeLine;
+
+fn setup_crosshair(
+	mut commands: Commands,
+	mut windows: ResMut<Windows>,
+	materials: Res<CrosshairMaterials>,
+) {
+	if let Some(window) = windows.get_primary_mut() {
+		window.set_cursor_visibility(false);
+	}
+
+	commands
+		.spawn_bundle(ImageBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				size: Size::new(Val::Px(CROSSHAIR_SIZE.0), Val::Px(CROSSHAIR_SIZE.1)),
+				..Default::default()
+			},
+			material: materials.ready.clone(),
+			visible: Visible {
+				is_visible: false,
+				is_transparent: true,
+			},
+			..Default::default()
+		})
+		.insert(Crosshair)
+		.insert(ActiveStateScoped(AppState::Game));
+	commands
+		.spawn_bundle(SpriteBundle {
+			material: materials.range_line.clone(),
+			sprite: Sprite::new(Vec2::ZERO),
+			visible: Visible {
+				is_visible: false,
+				is_transparent: true,
+			},
+			..Default::default()
+		})
+		.insert(RangeLine)
+		.insert(ActiveStateScoped(AppState::Game));
+}
+
+/// Brings back the OS cursor the crosshair replaced.
+fn show_cursor(mut windows: ResMut<Windows>) {
+	if let Some(window) = windows.get_primary_mut() {
+		window.set_cursor_visibility(true);
+	}
+}
+
+fn update_aim(
+	mut cursor_events: EventReader<CursorMoved>,
+	cursor: Res<CursorWorldPosition>,
+	controls: Res<PlayerControls>,
+	axes: Res<Axis<GamepadAxis>>,
+	mut aim: ResMut<Aim>,
+	players: Query<(&Player, &Transform)>,
+) {
+	if cursor_events.iter().next().is_some() {
+		aim.source = AimSource::Mouse;
+	}
+	if let Some(Controls::Primary(Some(gamepad))) = controls.0.first().copied() {
+		let axis = |axis_type| axes.get(GamepadAxis(gamepad, axis_type)).unwrap_or(0.0);
+		let stick = Vec2::new(
+			axis(GamepadAxisType::RightStickX),
+			axis(GamepadAxisType::RightStickY),
+		);
+		if stick.length() > GAMEPAD_AIM_DEAD_ZONE {
+			aim.source = AimSource::Gamepad;
+			aim.gamepad_direction = stick.normalize();
+		}
+	}
+
+	aim.position = match aim.source {
+		AimSource::Mouse => cursor.0,
+		AimSource::Gamepad => {
+			players
+				.iter()
+				.find(|(Player(player), _)| *player == 0)
+				.map(|(_, player)| {
+					player.translation.xy() + aim.gamepad_direction * GAMEPAD_AIM_DISTANCE
+				})
+		}
+	};
+}
+
+fn update_crosshair(
+	windows: Res<Windows>,
+	aim: Res<Aim>,
+	materials: Res<CrosshairMaterials>,
+	players: Query<(&Player, &Weapon)>,
+	cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+	mut crosshairs: Query<(&mut Style, &mut Visible, &mut Handle<ColorMaterial>), With<Crosshair>>,
+) {
+	let screen_position = cameras
+		.iter()
+		.next()
+		.and_then(|(camera, camera_transform)| {
+			let window = windows.get(camera.window)?;
+			match aim.source {
+				AimSource::Mouse => window.cursor_position(),
+				AimSource::Gamepad => aim
+					.position
+					.map(|position| world_to_screen(window, camera, camera_transform, position)),
+			}
+		});
+
+	let ready = players
+		.iter()
+		.find(|(Player(player), _)| *player == 0)
+		.map_or(1.0, |(_, weapon)| {
+			1.0 - weapon.cooldown_left / weapon.cooldown.max(f32::EPSILON)
+		});
+	let scale = COOLING_CROSSHAIR_SCALE + (1.0 - COOLING_CROSSHAIR_SCALE) * ready;
+	let size = Vec2::from(CROSSHAIR_SIZE) * scale;
+	let material = if ready >= 1.0 {
+		&materials.ready
+	} else {
+		&materials.cooling
+	};
+
+	for (mut style, mut visible, mut crosshair_material) in crosshairs.iter_mut() {
+		visible.is_visible = screen_position.is_some();
+		let screen_position = match screen_position {
+			Some(screen_position) => screen_position,
+			None => continue,
+		};
+		// Center the crosshair on the aim position.
+		let position = Rect {
+			left: Val::Px((screen_position.x - size.x / 2.0).round()),
+			bottom: Val::Px((screen_position.y - size.y / 2.0).round()),
+			..Default::default()
+		};
+		if style.position != position {
+			style.position = position;
+		}
+		let node_size = Size::new(Val::Px(size.x.round()), Val::Px(size.y.round()));
+		if style.size != node_size {
+			style.size = node_size;
+		}
+		if *crosshair_material != *material {
+			*crosshair_material = material.clone();
+		}
+	}
+}
+
+fn update_range_line(
+	aim: Res<Aim>,
+	players: Query<(&Player, &Transform, &Weapon), Without<RangeLine>>,
+	mut lines: Query<(&mut Transform, &mut Sprite, &mut Visible), With<RangeLine>>,
+) {
+	let line = players
+		.iter()
+		.find(|(Player(player), ..)| *player == 0)
+		.and_then(|(_, player, weapon)| Some((player.translation, weapon.range?, aim.position?)));
+
+	for (mut transform, mut sprite, mut visible) in lines.iter_mut() {
+		let (player, range, target) = match line {
+			Some(line) => line,
+			None => {
+				visible.is_visible = false;
+				continue;
+			}
+		};
+		let offset = target - player.xy();
+		let length = offset.length().min(range);
+		let direction = offset.normalize_or_zero();
+		visible.is_visible = length > 0.0;
+		sprite.size = Vec2::new(length, RANGE_LINE_WIDTH);
+		transform.translation = (player.xy() + direction * length / 2.0).extend(player.z + 0.5);
+		transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+	}
+}
@@ -0,0 +1,335 @@
+//! Seeded generator of dungeon levels made of rooms and corridors.
+
+use crate::components::LevelType;
+use crate::level::{EntityData, EntityKind, LevelData, TileKind};
+use std::collections::VecDeque;
+
+const WIDTH: usize = 40;
+const HEIGHT: usize = 30;
+const ROOM_ATTEMPTS: usize = 60;
+const MAX_ROOMS: usize = 9;
+const MIN_ROOM_SIZE: usize = 4;
+const MAX_ROOM_SIZE: usize = 9;
+const SPIKES_PER_ROOM: usize = 2;
+const MAX_ENEMIES_PER_ROOM: usize = 2;
+
+/// State of the endless dungeon behind the `Secret1` portal.
+pub struct Dungeon {
+	pub seed: u64,
+	/// How many dungeon floors the player went down in a row.
+	pub depth: u64,
+}
+
+impl Dungeon {
+	/// Generates the floor at the current depth.
+	pub fn generate(&self) -> LevelData {
+		generate(
+			self.seed
+				.wrapping_add(self.depth.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+		)
+	}
+}
+
+/// SplitMix64, good enough for level generation and identical on every platform.
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+
+	/// A number in `range.start..range.end`.
+	fn range(&mut self, range: std::ops::Range<usize>) -> usize {
+		range.start + (self.next() % (range.end - range.start) as u64) as usize
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Room {
+	column: usize,
+	row: usize,
+	width: usize,
+	height: usize,
+}
+
+impl Room {
+	fn center(&self) -> (usize, usize) {
+		(self.row + self.height / 2, self.column + self.width / 2)
+	}
+
+	/// Whether the rooms overlap or touch, so that there's always a wall between them.
+	fn touches(&self, other: &Room) -> bool {
+		self.column <= other.column + other.width
+			&& other.column <= self.column + self.width
+			&& self.row <= other.row + other.height
+			&& other.row <= self.row + self.height
+	}
+
+	fn tiles(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+		(self.row..self.row + self.height)
+			.flat_map(move |row| (self.column..self.column + self.width).map(move |c| (row, c)))
+	}
+}
+
+/// Rows from top to bottom of `Some(tile)` or `None` for nothing.
+type Grid = Vec<Vec<Option<TileKind>>>;
+
+/// Generates a level with a start checkpoint and a portal back to the hub in the first room, and a
+/// portal one floor deeper in the room farthest away. Spikes and enemies never block the way
+/// between them.
+pub fn generate(seed: u64) -> LevelData {
+	let mut rng = Rng(seed);
+	let mut grid: Grid = vec![vec![None; WIDTH]; HEIGHT];
+
+	let mut rooms = Vec::<Room>::new();
+	for _ in 0..ROOM_ATTEMPTS {
+		if rooms.len() == MAX_ROOMS {
+			break;
+		}
+		let width = rng.range(MIN_ROOM_SIZE..MAX_ROOM_SIZE + 1);
+		let height = rng.range(MIN_ROOM_SIZE..MAX_ROOM_SIZE + 1);
+		let room = Room {
+			column: rng.range(0..WIDTH - width + 1),
+			row: rng.range(0..HEIGHT - height + 1),
+			width,
+			height,
+		};
+		if rooms.iter().any(|other| room.touches(other)) {
+			continue;
+		}
+		for (row, column) in room.tiles() {
+			grid[row][column] = Some(TileKind::Ground);
+		}
+		if let Some(previous) = rooms.last() {
+			carve_corridor(&mut grid, &mut rng, previous.center(), room.center());
+		}
+		rooms.push(room);
+	}
+
+	let start_room = rooms[0];
+	let start = start_room.center();
+	let hub_portal = (start_room.row, start_room.column);
+	let distances = distances_from(&grid, start, &[]);
+	// The farthest reachable room center. Every room is connected, so it's never unreachable.
+	let exit = rooms
+		.iter()
+		.skip(1)
+		.map(Room::center)
+		.max_by_key(|&(row, column)| distances[row][column].unwrap_or(0))
+		.unwrap_or((
+			start_room.row + start_room.height - 1,
+			start_room.column + start_room.width - 1,
+		));
+
+	let mut blocked = vec![start, hub_portal, exit];
+	let mut entities = vec![
+		(start, EntityKind::Checkpoint { active: true }),
+		(hub_portal, EntityKind::Portal(LevelType::Hub)),
+		(exit, EntityKind::Portal(LevelType::Secret1)),
+	];
+
+	let mut candidates = Vec::new();
+	for room in rooms.iter().skip(1) {
+		let enemies = rng.range(0..MAX_ENEMIES_PER_ROOM + 1);
+		for idx in 0..SPIKES_PER_ROOM + enemies {
+			let kind = if idx >= SPIKES_PER_ROOM {
+				EntityKind::Enemy
+			} else if rng.next() % 2 == 0 {
+				EntityKind::Spikes
+			} else {
+				EntityKind::TimedSpikes
+			};
+			let tile = (
+				rng.range(room.row..room.row + room.height),
+				rng.range(room.column..room.column + room.width),
+			);
+			candidates.push((tile, kind));
+		}
+	}
+	let mut hazards = Vec::new();
+	for (tile, kind) in candidates {
+		if blocked.contains(&tile) {
+			continue;
+		}
+		blocked.push(tile);
+		hazards.push(tile);
+		// Drop hazards that would cut the way to the exit.
+		if distances_from(&grid, start, &hazards)[exit.0][exit.1].is_none() {
+			hazards.pop();
+			continue;
+		}
+		entities.push((tile, kind));
+	}
+
+	let mut level = LevelData {
+		tiles: grid
+			.iter()
+			.map(|row| {
+				row.iter()
+					.map(|tile| tile.map_or('.', TileKind::to_char))
+					.collect()
+			})
+			.collect(),
+		entities: Vec::new(),
+	};
+	level.entities = entities
+		.into_iter()
+		.map(|((row, column), kind)| EntityData {
+			position: level.tile_position(row, column),
+			kind,
+		})
+		.collect();
+	level
+}
+
+/// Carves an L shaped corridor between two tiles.
+fn carve_corridor(grid: &mut Grid, rng: &mut Rng, from: (usize, usize), to: (usize, usize)) {
+	let corner = if rng.next() % 2 == 0 {
+		(from.0, to.1)
+	} else {
+		(to.0, from.1)
+	};
+	for &(a, b) in [(from, corner), (corner, to)].iter() {
+		for row in a.0.min(b.0)..=a.0.max(b.0) {
+			for column in a.1.min(b.1)..=a.1.max(b.1) {
+				grid[row][column].get_or_insert(TileKind::Ground);
+			}
+		}
+	}
+}
+
+/// Flood fills walkable tiles from `start`, avoiding `avoid`, and returns the distance to each tile.
+fn distances_from(
+	grid: &Grid,
+	start: (usize, usize),
+	avoid: &[(usize, usize)],
+) -> Vec<Vec<Option<usize>>> {
+	let mut distances = vec![vec![None; WIDTH]; HEIGHT];
+	distances[start.0][start.1] = Some(0);
+	let mut queue = VecDeque::new();
+	queue.push_back(start);
+	while let Some((row, column)) = queue.pop_front() {
+		let distance = distances[row][column].unwrap_or(0);
+		let neighbors = [
+			(row.wrapping_sub(1), column),
+			(row + 1, column),
+			(row, column.wrapping_sub(1)),
+			(row, column + 1),
+		];
+		for &(row, column) in neighbors.iter() {
+			let walkable = grid
+				.get(row)
+				.and_then(|tiles| tiles.get(column))
+				.map_or(false, |tile| tile.map_or(false, TileKind::is_walkable));
+			if walkable && distances[row][column].is_none() && !avoid.contains(&(row, column)) {
+				distances[row][column] = Some(distance + 1);
+				queue.push_back((row, column));
+			}
+		}
+	}
+	distances
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	const SEEDS: std::ops::Range<u64> = 0..200;
+
+	fn grid(level: &LevelData) -> Grid {
+		(0..level.rows())
+			.map(|row| {
+				(0..level.columns())
+					.map(|column| level.tile(level.tile_position(row, column)))
+					.collect()
+			})
+			.collect()
+	}
+
+	/// Row and column of the entity at `position` in tile coordinates.
+	fn tile(level: &LevelData, position: (i32, i32)) -> (usize, usize) {
+		(0..level.rows())
+			.flat_map(|row| (0..level.columns()).map(move |column| (row, column)))
+			.find(|&(row, column)| level.tile_position(row, column) == position)
+			.unwrap()
+	}
+
+	fn find(level: &LevelData, kind: EntityKind) -> (usize, usize) {
+		let entity = level
+			.entities
+			.iter()
+			.find(|entity| entity.kind == kind)
+			.unwrap_or_else(|| panic!("no {:?}", kind));
+		tile(level, entity.position)
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn floors_depend_only_on_the_seed() {
+		assert_eq!(generate(7), generate(7));
+		assert_ne!(generate(7), generate(8));
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn portals_are_reachable_from_the_spawn() {
+		for seed in SEEDS {
+			let level = generate(seed);
+			let grid = grid(&level);
+			assert_eq!((grid.len(), grid[0].len()), (HEIGHT, WIDTH));
+			let start = find(&level, EntityKind::Checkpoint { active: true });
+			let hazards = level
+				.entities
+				.iter()
+				.filter(|entity| {
+					matches!(
+						entity.kind,
+						EntityKind::Spikes | EntityKind::TimedSpikes | EntityKind::Enemy
+					)
+				})
+				.map(|entity| tile(&level, entity.position))
+				.collect::<Vec<_>>();
+			let distances = distances_from(&grid, start, &hazards);
+			for &destination in [LevelType::Hub, LevelType::Secret1].iter() {
+				let (row, column) = find(&level, EntityKind::Portal(destination));
+				assert!(
+					distances[row][column].is_some(),
+					"{:?} portal unreachable with seed {}",
+					destination,
+					seed
+				);
+			}
+		}
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn every_floor_tile_is_connected() {
+		for seed in SEEDS {
+			let level = generate(seed);
+			let grid = grid(&level);
+			let distances = distances_from(
+				&grid,
+				find(&level, EntityKind::Checkpoint { active: true }),
+				&[],
+			);
+			for (row, tiles) in grid.iter().enumerate() {
+				for (column, tile) in tiles.iter().enumerate() {
+					assert_eq!(
+						tile.is_some(),
+						distances[row][column].is_some(),
+						"tile {:?} with seed {}",
+						(row, column),
+						seed
+					);
+				}
+			}
+		}
+	}
+}
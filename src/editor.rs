@@ -0,0 +1,526 @@
+use crate::camera::CursorWorldPosition;
+use crate::components::*;
+use crate::console::{ConsoleCommand, ConsoleEvent};
+use crate::level::{
+	world_to_tile, ConveyorDirection, EntityKind, LevelData, LevelHandles, LevelSpawner, TileKind,
+};
+use crate::ui::StateScoped;
+use crate::AppState;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::path::PathBuf;
+
+/// Console command that opens and closes the editor.
+static EDITOR_COMMAND: &str = "editor";
+static HELP: &str = "1-0 brush, Tab property, LMB paint, RMB erase, WASD pan, \
+	Ctrl+Z undo, Ctrl+Y redo, Ctrl+S save";
+const PALETTE_ICON_SIZE: f32 = 40.0;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<Editor>()
+			.init_resource::<EditorMaterials>()
+			.add_system(toggle_editor.system())
+			.add_system_set(
+				SystemSet::on_enter(AppState::Editor).with_system(setup_editor.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Editor)
+					.with_system(select_brush.system())
+					.with_system(paint.system())
+					.with_system(undo_redo.system())
+					.with_system(save_level.system())
+					.with_system(update_palette.system()),
+			);
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Brush {
+	Tile(TileKind),
+	Spikes,
+	Portal,
+	Checkpoint,
+	Enemy,
+	TimedSpikes,
+	Conveyor,
+	Platform,
+}
+
+/// Palette entries: brush, name and icon.
+const BRUSHES: [(Brush, &str, &str); 11] = [
+	(Brush::Tile(TileKind::Ground), "Ground", "ground.png"),
+	(
+		Brush::Tile(TileKind::TransparentGround),
+		"Transparent ground",
+		"transparent_ground.png",
+	),
+	(Brush::Spikes, "Spikes", "spikes.png"),
+	(Brush::Portal, "Portal", "portal.png"),
+	(Brush::Checkpoint, "Checkpoint", "red_tree.png"),
+	(Brush::Tile(TileKind::Water), "Water", "water.png"),
+	(Brush::Tile(TileKind::Bridge), "Bridge", "bridge.png"),
+	(Brush::Enemy, "Enemy", "bird.png"),
+	(Brush::TimedSpikes, "Timed spikes", "spikes.png"),
+	(Brush::Conveyor, "Conveyor", "conveyor.png"),
+	(Brush::Platform, "Moving platform", "bridge.png"),
+];
+
+const BRUSH_KEYS: [KeyCode; 10] = [
+	KeyCode::Key1,
+	KeyCode::Key2,
+	KeyCode::Key3,
+	KeyCode::Key4,
+	KeyCode::Key5,
+	KeyCode::Key6,
+	KeyCode::Key7,
+	KeyCode::Key8,
+	KeyCode::Key9,
+	KeyCode::Key0,
+];
+
+/// Editor state that survives closing and reopening the editor.
+struct Editor {
+	/// Index into [`BRUSHES`].
+	brush: usize,
+	portal_destination: LevelType,
+	checkpoint_active: bool,
+	conveyor_direction: ConveyorDirection,
+	history: HashMap<LevelType, History>,
+	/// Whether the current mouse stroke already saved an undo step.
+	stroke: bool,
+}
+
+impl Default for Editor {
+	fn default() -> Self {
+		Editor {
+			brush: 0,
+			portal_destination: LevelType::Hub,
+			checkpoint_active: false,
+			conveyor_direction: ConveyorDirection::Right,
+			history: HashMap::default(),
+			stroke: false,
+		}
+	}
+}
+
+impl Editor {
+	fn brush(&self) -> Brush {
+		BRUSHES[self.brush].0
+	}
+
+	fn description(&self) -> String {
+		let name = BRUSHES[self.brush].1;
+		match self.brush() {
+			Brush::Portal => format!("{} to {}", name, self.portal_destination),
+			Brush::Checkpoint if self.checkpoint_active => format!("{} (start)", name),
+			Brush::Conveyor => format!("{} {}", name, self.conveyor_direction),
+			_ => name.to_string(),
+		}
+	}
+
+	/// Paints the selected brush at `position`. Returns whether the level changed.
+	fn paint(&self, data: &mut LevelData, position: (i32, i32)) -> bool {
+		match self.brush() {
+			Brush::Tile(tile) => data.set_tile(position, Some(tile)),
+			Brush::Spikes => data.set_entity(position, Some(EntityKind::Spikes)),
+			Brush::Enemy => data.set_entity(position, Some(EntityKind::Enemy)),
+			Brush::TimedSpikes => data.set_entity(position, Some(EntityKind::TimedSpikes)),
+			Brush::Platform => data.set_entity(position, Some(EntityKind::Platform)),
+			Brush::Conveyor => {
+				data.set_tile(position, Some(TileKind::Conveyor(self.conveyor_direction)))
+			}
+			Brush::Portal => {
+				data.set_entity(position, Some(EntityKind::Portal(self.portal_destination)))
+			}
+			Brush::Checkpoint => data.set_entity(
+				position,
+				Some(EntityKind::Checkpoint {
+					active: self.checkpoint_active,
+				}),
+			),
+		}
+	}
+}
+
+/// Undo and redo steps of a single level.
+#[derive(Default)]
+struct History {
+	undo: Vec<LevelData>,
+	redo: Vec<LevelData>,
+	/// The level as the editor left it. The steps don't apply anymore once it changes outside of
+	/// the editor, e.g. on a new dungeon floor or after a hot reload.
+	edited: Option<LevelData>,
+}
+
+struct EditorMaterials {
+	transparent: Handle<ColorMaterial>,
+	frame: Handle<ColorMaterial>,
+	selected_frame: Handle<ColorMaterial>,
+	icons: Vec<Handle<ColorMaterial>>,
+}
+
+impl FromWorld for EditorMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let asset_server = world.get_resource::<AssetServer>().unwrap();
+		let textures = BRUSHES
+			.iter()
+			.map(|(_, _, texture)| asset_server.load(*texture))
+			.collect::<Vec<Handle<Texture>>>();
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		EditorMaterials {
+			transparent: materials.add(Color::NONE.into()),
+			frame: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.5).into()),
+			selected_frame: materials.add(Color::rgb(0.9, 0.75, 0.2).into()),
+			icons: textures
+				.into_iter()
+				.map(|texture| materials.add(texture.into()))
+				.collect(),
+		}
+	}
+}
+
+/// Frame around a palette button, highlighted when its brush is selected.
+struct PaletteFrame(usize);
+
+struct PaletteButton(usize);
+
+struct BrushText;
+
+fn toggle_editor(
+	mut commands: EventReader<ConsoleCommand>,
+	mut state: ResMut<State<AppState>>,
+	mut console_events: EventWriter<ConsoleEvent>,
+) {
+	for ConsoleCommand(command) in commands.iter() {
+		if command != EDITOR_COMMAND {
+			continue;
+		}
+		let result = match state.current() {
+			// Pause the game instead of leaving it, so that it continues where it was.
+			AppState::Game => state.push(AppState::Editor),
+			AppState::Editor if state.inactives().is_empty() => state.set(AppState::Game),
+			AppState::Editor => state.pop(),
+			_ => {
+				console_events.send(ConsoleEvent::from("The editor opens only in game\n"));
+				continue;
+			}
+		};
+		if let Err(e) = result {
+			warn!("Can't toggle the editor: {:?}", e);
+		}
+	}
+}
+
+fn setup_editor(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	materials: Res<EditorMaterials>,
+	mut editor: ResMut<Editor>,
+	mut spawner: ResMut<LevelSpawner>,
+	handles: Res<LevelHandles>,
+	levels: Res<Assets<LevelData>>,
+) {
+	info!("Opening the level editor");
+	editor
+		.history
+		.retain(|l_type, history| history.edited.as_ref() == handles.get(*l_type, &levels));
+	// The level isn't spawned yet when starting in the editor.
+	spawner.respawn();
+
+	let font = asset_server.load("FiraSans-Bold.ttf");
+	let text = |value: &str| TextBundle {
+		text: Text::with_section(
+			value,
+			TextStyle {
+				font: font.clone(),
+				font_size: 20.0,
+				color: Color::rgb(0.9, 0.9, 0.9),
+			},
+			TextAlignment::default(),
+		),
+		..Default::default()
+	};
+
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				position: Rect {
+					left: Val::Px(0.0),
+					bottom: Val::Percent(2.0),
+					..Default::default()
+				},
+				size: Size::new(Val::Percent(100.0), Val::Auto),
+				flex_direction: FlexDirection::ColumnReverse,
+				align_items: AlignItems::Center,
+				..Default::default()
+			},
+			material: materials.transparent.clone(),
+			..Default::default()
+		})
+		.insert(StateScoped(AppState::Editor))
+		.with_children(|parent| {
+			parent.spawn_bundle(text(HELP));
+			parent
+				.spawn_bundle(text(&editor.description()))
+				.insert(BrushText);
+			parent
+				.spawn_bundle(NodeBundle {
+					material: materials.transparent.clone(),
+					..Default::default()
+				})
+				.with_children(|parent| {
+					for (idx, icon) in materials.icons.iter().enumerate() {
+						parent
+							.spawn_bundle(NodeBundle {
+								style: Style {
+									padding: Rect::all(Val::Px(3.0)),
+									margin: Rect::all(Val::Px(2.0)),
+									..Default::default()
+								},
+								material: materials.frame.clone(),
+								..Default::default()
+							})
+							.insert(PaletteFrame(idx))
+							.with_children(|parent| {
+								parent
+									.spawn_bundle(ButtonBundle {
+										style: Style {
+											size: Size::new(
+												Val::Px(PALETTE_ICON_SIZE),
+												Val::Px(PALETTE_ICON_SIZE),
+											),
+											..Default::default()
+										},
+										material: icon.clone(),
+										..Default::default()
+									})
+									.insert(PaletteButton(idx));
+							});
+					}
+				});
+		});
+}
+
+fn select_brush(
+	kb_input: Res<Input<KeyCode>>,
+	mut editor: ResMut<Editor>,
+	buttons: Query<(&Interaction, &PaletteButton), Changed<Interaction>>,
+) {
+	for (key_idx, key) in BRUSH_KEYS.iter().enumerate() {
+		if kb_input.just_pressed(*key) {
+			editor.brush = key_idx;
+		}
+	}
+	for (interaction, PaletteButton(idx)) in buttons.iter() {
+		if *interaction == Interaction::Clicked {
+			editor.brush = *idx;
+		}
+	}
+
+	if kb_input.just_pressed(KeyCode::Tab) {
+		match editor.brush() {
+			Brush::Portal => {
+				let types = LevelType::ALL;
+				let current = types
+					.iter()
+					.position(|l_type| *l_type == editor.portal_destination)
+					.unwrap_or(0);
+				editor.portal_destination = types[(current + 1) % types.len()];
+			}
+			Brush::Checkpoint => editor.checkpoint_active = !editor.checkpoint_active,
+			Brush::Conveyor => {
+				let directions = ConveyorDirection::ALL;
+				let current = directions
+					.iter()
+					.position(|direction| *direction == editor.conveyor_direction)
+					.unwrap_or(0);
+				editor.conveyor_direction = directions[(current + 1) % directions.len()];
+			}
+			_ => {}
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn paint(
+	mouse_input: Res<Input<MouseButton>>,
+	cursor: Res<CursorWorldPosition>,
+	level: Res<Level>,
+	handles: Res<LevelHandles>,
+	mut levels: ResMut<Assets<LevelData>>,
+	mut editor: ResMut<Editor>,
+	mut spawner: ResMut<LevelSpawner>,
+	buttons: Query<&Interaction, With<PaletteButton>>,
+) {
+	let erase = mouse_input.pressed(MouseButton::Right);
+	if !mouse_input.pressed(MouseButton::Left) && !erase {
+		editor.stroke = false;
+		return;
+	}
+	// Clicks on the palette don't paint.
+	if buttons
+		.iter()
+		.any(|interaction| *interaction != Interaction::None)
+	{
+		return;
+	}
+	let position = match cursor.0 {
+		Some(cursor) => world_to_tile(cursor),
+		None => return,
+	};
+	let handle = match handles.0.get(&level.l_type) {
+		Some(handle) => handle,
+		None => return,
+	};
+	let data = match levels.get(handle) {
+		Some(data) => data,
+		None => return,
+	};
+
+	let mut edited = data.clone();
+	let changed = if erase {
+		if edited.entity(position).is_some() {
+			edited.set_entity(position, None)
+		} else {
+			edited.set_tile(position, None)
+		}
+	} else {
+		editor.paint(&mut edited, position)
+	};
+	if !changed {
+		return;
+	}
+
+	// A whole stroke is undone at once.
+	let editor = &mut *editor;
+	let history = editor.history.entry(level.l_type).or_default();
+	if !editor.stroke {
+		editor.stroke = true;
+		history.undo.push(data.clone());
+		history.redo.clear();
+	}
+	history.edited = Some(edited.clone());
+	*levels.get_mut(handle).unwrap() = edited;
+	spawner.respawn();
+}
+
+fn undo_redo(
+	kb_input: Res<Input<KeyCode>>,
+	level: Res<Level>,
+	handles: Res<LevelHandles>,
+	mut levels: ResMut<Assets<LevelData>>,
+	mut editor: ResMut<Editor>,
+	mut spawner: ResMut<LevelSpawner>,
+) {
+	let ctrl = kb_input.pressed(KeyCode::LControl) || kb_input.pressed(KeyCode::RControl);
+	let shift = kb_input.pressed(KeyCode::LShift) || kb_input.pressed(KeyCode::RShift);
+	let undo = ctrl && !shift && kb_input.just_pressed(KeyCode::Z);
+	let redo =
+		ctrl && (kb_input.just_pressed(KeyCode::Y) || (shift && kb_input.just_pressed(KeyCode::Z)));
+	if !undo && !redo {
+		return;
+	}
+	let data = match handles
+		.0
+		.get(&level.l_type)
+		.and_then(|handle| levels.get_mut(handle))
+	{
+		Some(data) => data,
+		None => return,
+	};
+
+	let history = match editor.history.get_mut(&level.l_type) {
+		Some(history) => history,
+		None => return,
+	};
+
+	let (from, to) = if undo {
+		(&mut history.undo, &mut history.redo)
+	} else {
+		(&mut history.redo, &mut history.undo)
+	};
+	if let Some(previous) = from.pop() {
+		to.push(std::mem::replace(data, previous));
+		history.edited = Some(data.clone());
+		spawner.respawn();
+	}
+}
+
+fn save_level(
+	kb_input: Res<Input<KeyCode>>,
+	level: Res<Level>,
+	handles: Res<LevelHandles>,
+	levels: Res<Assets<LevelData>>,
+	mut console_events: EventWriter<ConsoleEvent>,
+) {
+	let ctrl = kb_input.pressed(KeyCode::LControl) || kb_input.pressed(KeyCode::RControl);
+	if !ctrl || !kb_input.just_pressed(KeyCode::S) {
+		return;
+	}
+	let data = match handles.get(level.l_type, &levels) {
+		Some(data) => data,
+		None => return,
+	};
+	match write_level_file(level.l_type, data) {
+		Ok(path) => {
+			info!("Saved level {:?} to {}", level.l_type, path.display());
+			console_events.send(ConsoleEvent::Log(format!("Saved {}\n", path.display())));
+		}
+		Err(e) => {
+			error!("Failed to save level {:?}: {}", level.l_type, e);
+			console_events.send(ConsoleEvent::Log(format!("Save failed: {}\n", e)));
+		}
+	}
+}
+
+/// Writes the level file into the assets directory the game loads it from.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_level_file(l_type: LevelType, data: &LevelData) -> Result<PathBuf, String> {
+	let text = ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::new())
+		.map_err(|e| e.to_string())?;
+	// Same root as the asset server uses.
+	let root = std::env::var_os("CARGO_MANIFEST_DIR")
+		.map(PathBuf::from)
+		.or_else(|| Some(std::env::current_exe().ok()?.parent()?.to_path_buf()))
+		.unwrap_or_default();
+	let file_name = l_type
+		.file_name()
+		.ok_or_else(|| format!("{} is generated and has no level file", l_type))?;
+	let path = root.join("assets").join(file_name);
+	std::fs::write(&path, text).map_err(|e| e.to_string())?;
+	Ok(path)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_level_file(_l_type: LevelType, _data: &LevelData) -> Result<PathBuf, String> {
+	Err("saving levels isn't supported on the web".to_string())
+}
+
+fn update_palette(
+	editor: Res<Editor>,
+	level: Res<Level>,
+	materials: Res<EditorMaterials>,
+	mut frames: Query<(&PaletteFrame, &mut Handle<ColorMaterial>)>,
+	mut texts: Query<&mut Text, With<BrushText>>,
+) {
+	for (PaletteFrame(idx), mut material) in frames.iter_mut() {
+		let selected = if *idx == editor.brush {
+			&materials.selected_frame
+		} else {
+			&materials.frame
+		};
+		if *material != *selected {
+			*material = selected.clone();
+		}
+	}
+	let description = format!("{}: {}", level.l_type, editor.description());
+	for mut text in texts.iter_mut() {
+		if text.sections[0].value != description {
+			text.sections[0].value = description.clone();
+		}
+	}
+}
@@ -0,0 +1,102 @@
+use crate::camera::CameraShake;
+use crate::components::{MainCamera, TILE_SIZE};
+use crate::{AppState, CollisionEvent};
+use bevy::prelude::*;
+
+/// Trauma added when the player touches a hazard.
+const HAZARD_TRAUMA: f32 = 0.6;
+/// Trauma added when a missile explodes.
+const EXPLOSION_TRAUMA: f32 = 0.25;
+/// Explosions farther away from the camera than this don't shake it.
+const EXPLOSION_RANGE: f32 = 10.0 * TILE_SIZE;
+/// How long the game freezes when the player touches a hazard, in seconds.
+const HAZARD_HIT_STOP: f32 = 0.15;
+/// How long the game freezes when a missile explodes, in seconds.
+const EXPLOSION_HIT_STOP: f32 = 0.04;
+/// Time scale during a hit-stop. Not zero, so that the game doesn't look frozen.
+const HIT_STOP_TIME_SCALE: f32 = 0.05;
+
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_event::<ExplosionEvent>()
+			.insert_resource(TimeScale(1.0))
+			.init_resource::<HitStop>()
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(trigger_impact_effects.system())
+					.with_system(update_hit_stop.system()),
+			)
+			.add_system_set(
+				SystemSet::on_exit(AppState::Game).with_system(reset_hit_stop.system()),
+			);
+	}
+}
+
+/// Multiplier for the gameplay time step. Lowered during hit-stops.
+pub struct TimeScale(pub f32);
+
+/// Sent when a missile hits something.
+pub struct ExplosionEvent {
+	pub position: Vec3,
+}
+
+/// Remaining real time of the current hit-stop.
+#[derive(Default)]
+struct HitStop(f32);
+
+fn trigger_impact_effects(
+	mut collision_events: EventReader<CollisionEvent>,
+	mut explosion_events: EventReader<ExplosionEvent>,
+	mut hit_stop: ResMut<HitStop>,
+	mut camera_query: Query<(&Transform, &mut CameraShake), With<MainCamera>>,
+) {
+	let mut stop: f32 = 0.0;
+	let hazard_hits = collision_events
+		.iter()
+		.filter(|collision_event| matches!(collision_event, CollisionEvent::Hazard { .. }))
+		.count();
+	if hazard_hits > 0 {
+		stop = stop.max(HAZARD_HIT_STOP);
+	}
+	let explosions = explosion_events
+		.iter()
+		.map(|explosion| explosion.position)
+		.collect::<Vec<_>>();
+	if !explosions.is_empty() {
+		stop = stop.max(EXPLOSION_HIT_STOP);
+	}
+	hit_stop.0 = hit_stop.0.max(stop);
+
+	for (camera_transform, mut shake) in camera_query.iter_mut() {
+		let mut trauma = HAZARD_TRAUMA * hazard_hits as f32;
+		for position in explosions.iter() {
+			let distance = position
+				.truncate()
+				.distance(camera_transform.translation.truncate());
+			trauma += EXPLOSION_TRAUMA * (1.0 - distance / EXPLOSION_RANGE).max(0.0);
+		}
+		if trauma > 0.0 {
+			shake.add_trauma(trauma);
+		}
+	}
+}
+
+fn update_hit_stop(
+	time: Res<Time>,
+	mut hit_stop: ResMut<HitStop>,
+	mut time_scale: ResMut<TimeScale>,
+) {
+	hit_stop.0 = (hit_stop.0 - time.delta_seconds()).max(0.0);
+	time_scale.0 = if hit_stop.0 > 0.0 {
+		HIT_STOP_TIME_SCALE
+	} else {
+		1.0
+	};
+}
+
+fn reset_hit_stop(mut hit_stop: ResMut<HitStop>, mut time_scale: ResMut<TimeScale>) {
+	hit_stop.0 = 0.0;
+	time_scale.0 = 1.0;
+}
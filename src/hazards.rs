@@ -0,0 +1,323 @@
+use crate::components::*;
+use crate::effects::TimeScale;
+use crate::AppState;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub struct HazardsPlugin;
+
+impl Plugin for HazardsPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_asset::<HazardConfig>()
+			.init_asset_loader::<HazardConfigLoader>()
+			.init_resource::<HazardMaterials>()
+			.add_startup_system(load_hazard_config.system())
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(retract_spikes.system())
+					.with_system(move_platforms.system())
+					.with_system(update_footing.system()),
+			);
+	}
+}
+
+/// Hazard tuning, loaded from `config/default.hazards`.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "b6a4f2d1-95c3-4e07-8a1e-6d2f0c7b3e58"]
+pub struct HazardConfig {
+	pub spikes_damage: u32,
+	pub timed_spikes_damage: u32,
+	/// Seconds the timed spikes stay out.
+	pub timed_spikes_extended: f32,
+	/// Seconds the timed spikes stay hidden.
+	pub timed_spikes_retracted: f32,
+	pub enemy_damage: u32,
+	/// Multiplier of the player speed while walking in water.
+	pub water_speed: f32,
+	/// Seconds the player can wade in water before drowning.
+	pub drowning_time: f32,
+	pub water_damage: u32,
+	/// Pixels per second.
+	pub conveyor_speed: f32,
+	/// Pixels per second.
+	pub platform_speed: f32,
+}
+
+impl Default for HazardConfig {
+	fn default() -> Self {
+		HazardConfig {
+			spikes_damage: 1,
+			timed_spikes_damage: 2,
+			timed_spikes_extended: 1.5,
+			timed_spikes_retracted: 1.5,
+			enemy_damage: 1,
+			water_speed: 0.5,
+			drowning_time: 1.0,
+			water_damage: 1,
+			conveyor_speed: 60.0,
+			platform_speed: 40.0,
+		}
+	}
+}
+
+pub struct HazardConfigHandle(pub Handle<HazardConfig>);
+
+impl HazardConfigHandle {
+	/// Returns the loaded config, or the defaults while it's still loading.
+	pub fn get(&self, configs: &Assets<HazardConfig>) -> HazardConfig {
+		configs.get(&self.0).cloned().unwrap_or_default()
+	}
+}
+
+#[derive(Default)]
+pub struct HazardConfigLoader;
+
+impl AssetLoader for HazardConfigLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let config = ron::de::from_bytes::<HazardConfig>(bytes)?;
+			load_context.set_default_asset(LoadedAsset::new(config));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["hazards"]
+	}
+}
+
+pub struct HazardMaterials {
+	pub retracted_spikes: Handle<ColorMaterial>,
+}
+
+impl FromWorld for HazardMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let texture = world
+			.get_resource::<AssetServer>()
+			.unwrap()
+			.load("spikes.png");
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		HazardMaterials {
+			retracted_spikes: materials.add(ColorMaterial::modulated_texture(
+				texture,
+				Color::rgba(1.0, 1.0, 1.0, 0.3),
+			)),
+		}
+	}
+}
+
+/// Hurts the player on touch while active.
+#[derive(Debug, Copy, Clone)]
+pub struct Hazard {
+	pub damage: u32,
+	pub active: bool,
+}
+
+impl Hazard {
+	pub fn new(damage: u32) -> Self {
+		Hazard {
+			damage,
+			active: true,
+		}
+	}
+}
+
+/// Spikes that go in and out of the ground on a timer.
+pub struct RetractingSpikes {
+	pub timer: Timer,
+	pub extended: f32,
+	pub retracted: f32,
+}
+
+impl RetractingSpikes {
+	/// Starts the phase where the spikes are out if `extended_first`, or hidden otherwise.
+	pub fn new(extended: f32, retracted: f32, extended_first: bool) -> Self {
+		let duration = if extended_first { extended } else { retracted };
+		RetractingSpikes {
+			timer: Timer::from_seconds(duration, false),
+			extended,
+			retracted,
+		}
+	}
+}
+
+/// Multiplies the walking speed of the player standing on this tile.
+pub struct SlowsPlayer(pub f32);
+
+/// Pushes the player standing on this tile, in pixels per second.
+pub struct Conveyor(pub Vec2);
+
+/// Water the player drowns in when staying too long, unless carried by a [`MovingPlatform`].
+pub struct Water;
+
+/// Goes back and forth between two points, carrying the player standing on it.
+pub struct MovingPlatform {
+	pub from: Vec2,
+	pub to: Vec2,
+	/// Pixels per second.
+	pub speed: f32,
+	/// Whether it's heading to `to`.
+	pub forward: bool,
+	pub velocity: Vec2,
+}
+
+impl MovingPlatform {
+	pub fn new(from: Vec2, to: Vec2, speed: f32) -> Self {
+		MovingPlatform {
+			from,
+			to,
+			speed,
+			forward: true,
+			velocity: Vec2::ZERO,
+		}
+	}
+}
+
+/// What the player is standing on.
+#[derive(Debug, Copy, Clone)]
+pub struct Footing {
+	pub speed: f32,
+	pub push: Vec2,
+	/// Seconds the player has been in water.
+	pub wading: f32,
+	/// Damage of the water the player just drowned in.
+	pub drowning: Option<u32>,
+}
+
+impl Default for Footing {
+	fn default() -> Self {
+		Footing {
+			speed: 1.0,
+			push: Vec2::ZERO,
+			wading: 0.0,
+			drowning: None,
+		}
+	}
+}
+
+fn load_hazard_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+	commands.insert_resource(HazardConfigHandle(
+		asset_server.load("config/default.hazards"),
+	));
+}
+
+fn retract_spikes(
+	time: Res<Time>,
+	time_scale: Res<TimeScale>,
+	textures: Res<Textures>,
+	materials: Res<HazardMaterials>,
+	mut spikes: Query<(
+		&mut RetractingSpikes,
+		&mut Hazard,
+		&mut Handle<ColorMaterial>,
+	)>,
+) {
+	let delta = std::time::Duration::from_secs_f32(time.delta_seconds() * time_scale.0);
+	for (mut spikes, mut hazard, mut material) in spikes.iter_mut() {
+		if !spikes.timer.tick(delta).just_finished() {
+			continue;
+		}
+		hazard.active = !hazard.active;
+		let (duration, next_material) = if hazard.active {
+			(spikes.extended, &textures.spikes_texture)
+		} else {
+			(spikes.retracted, &materials.retracted_spikes)
+		};
+		spikes.timer = Timer::from_seconds(duration, false);
+		*material = next_material.clone();
+	}
+}
+
+fn move_platforms(
+	time: Res<Time>,
+	time_scale: Res<TimeScale>,
+	mut platforms: Query<(&mut Transform, &mut MovingPlatform)>,
+) {
+	let delta = time.delta_seconds() * time_scale.0;
+	if delta == 0.0 {
+		return;
+	}
+	for (mut transform, mut platform) in platforms.iter_mut() {
+		let target = if platform.forward {
+			platform.to
+		} else {
+			platform.from
+		};
+		let offset = target - transform.translation.truncate();
+		let step = platform.speed * delta;
+		let moved = if offset.length() <= step {
+			platform.forward = !platform.forward;
+			offset
+		} else {
+			offset.normalize() * step
+		};
+		platform.velocity = moved / delta;
+		transform.translation.x += moved.x;
+		transform.translation.y += moved.y;
+	}
+}
+
+#[allow(clippy::type_complexity)]
+fn update_footing(
+	time: Res<Time>,
+	time_scale: Res<TimeScale>,
+	configs: Res<Assets<HazardConfig>>,
+	config: Res<HazardConfigHandle>,
+	tiles: Query<
+		(
+			&Transform,
+			Option<&SlowsPlayer>,
+			Option<&Conveyor>,
+			Option<&Water>,
+		),
+		Or<(With<SlowsPlayer>, With<Conveyor>, With<Water>)>,
+	>,
+	platforms: Query<(&Transform, &MovingPlatform)>,
+	mut players: Query<(&Transform, &mut Footing), With<Player>>,
+) {
+	let config = config.get(&configs);
+	let stands_on = |player: &Transform, tile: &Transform| {
+		let offset = (player.translation - tile.translation).abs();
+		offset.x < TILE_SIZE / 2.0 && offset.y < TILE_SIZE / 2.0
+	};
+	for (player, mut footing) in players.iter_mut() {
+		let mut next = Footing::default();
+		let mut in_water = false;
+		// Platforms float above the tiles.
+		match platforms
+			.iter()
+			.find(|(platform, _)| stands_on(player, platform))
+		{
+			Some((_, platform)) => next.push = platform.velocity,
+			None => {
+				for (tile, slows, conveyor, water) in tiles.iter() {
+					if !stands_on(player, tile) {
+						continue;
+					}
+					if let Some(SlowsPlayer(speed)) = slows {
+						next.speed = *speed;
+					}
+					if let Some(Conveyor(push)) = conveyor {
+						next.push = *push;
+					}
+					in_water |= water.is_some();
+				}
+			}
+		}
+		if in_water {
+			next.wading = footing.wading + time.delta_seconds() * time_scale.0;
+			if next.wading >= config.drowning_time {
+				next.wading = 0.0;
+				next.drowning = Some(config.water_damage);
+			}
+		}
+		*footing = next;
+	}
+}
@@ -0,0 +1,336 @@
+use crate::components::Score;
+use crate::i18n::Locale;
+use crate::menu::{spawn_menu, ButtonBehavior, ButtonMaterials, MenuFocus, MenuItem};
+use crate::replay::ActiveGamepad;
+use crate::storage;
+use crate::ui::StateScoped;
+use crate::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Saved in the working directory, or in the browser.
+static HIGH_SCORES_PATH: &str = "highscores.ron";
+const MAX_HIGH_SCORES: usize = 10;
+const MAX_NAME_LENGTH: usize = 12;
+
+static HIGH_SCORES_MENU: [MenuItem; 1] = [MenuItem {
+	label: "menu-back",
+	hover_label: "menu-back-hover",
+	pressed_label: "menu-back-pressed",
+	action: ButtonBehavior::Goto(AppState::Menu),
+}];
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.insert_resource(HighScores::load())
+			.init_resource::<HighScoreMaterials>()
+			.init_resource::<NameEntry>()
+			.add_system_set(
+				SystemSet::on_enter(AppState::GameOver).with_system(setup_game_over.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::GameOver).with_system(enter_name.system()),
+			)
+			.add_system_set(
+				SystemSet::on_enter(AppState::HighScores).with_system(setup_high_scores.system()),
+			);
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+	pub name: String,
+	pub score: u32,
+}
+
+/// The best scores on this machine, highest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HighScores(pub Vec<HighScore>);
+
+impl HighScores {
+	fn load() -> Self {
+		let text = match storage::read(HIGH_SCORES_PATH) {
+			Ok(text) => text,
+			Err(e) => {
+				info!("No high scores loaded from {}: {}", HIGH_SCORES_PATH, e);
+				return HighScores::default();
+			}
+		};
+		match ron::de::from_str(&text) {
+			Ok(high_scores) => high_scores,
+			Err(e) => {
+				warn!("Invalid high scores in {}: {}", HIGH_SCORES_PATH, e);
+				HighScores::default()
+			}
+		}
+	}
+
+	fn save(&self) -> Result<(), anyhow::Error> {
+		let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?;
+		storage::write(HIGH_SCORES_PATH, &text)
+	}
+
+	/// Whether `score` would make it into the table.
+	pub fn qualifies(&self, score: u32) -> bool {
+		score > 0
+			&& (self.0.len() < MAX_HIGH_SCORES
+				|| self.0.last().map_or(true, |lowest| score > lowest.score))
+	}
+
+	/// Adds the score below the equal ones already in the table and drops the lowest ones.
+	pub fn insert(&mut self, name: String, score: u32) {
+		let idx = self
+			.0
+			.iter()
+			.position(|high_score| high_score.score < score)
+			.unwrap_or_else(|| self.0.len());
+		self.0.insert(idx, HighScore { name, score });
+		self.0.truncate(MAX_HIGH_SCORES);
+	}
+}
+
+struct HighScoreMaterials {
+	transparent: Handle<ColorMaterial>,
+}
+
+impl FromWorld for HighScoreMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		HighScoreMaterials {
+			transparent: materials.add(Color::NONE.into()),
+		}
+	}
+}
+
+#[derive(Default)]
+struct NameEntry {
+	/// Name typed on the game over screen. `None` if the score didn't make it into the table.
+	name: Option<String>,
+	/// Characters typed in the frame the game ended were meant for the game.
+	skip_typed: bool,
+}
+
+struct NameText;
+
+fn setup_game_over(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	locale: Res<Locale>,
+	score: Res<Score>,
+	high_scores: Res<HighScores>,
+	materials: Res<HighScoreMaterials>,
+	mut name_entry: ResMut<NameEntry>,
+) {
+	let font = asset_server.load("FiraSans-Bold.ttf");
+	let text = |value: String, font_size: f32| TextBundle {
+		style: Style {
+			margin: Rect::all(Val::Px(10.0)),
+			..Default::default()
+		},
+		text: Text::with_section(
+			value,
+			TextStyle {
+				font: font.clone(),
+				font_size,
+				color: Color::rgb(0.9, 0.9, 0.9),
+			},
+			TextAlignment {
+				horizontal: HorizontalAlign::Center,
+				..Default::default()
+			},
+		),
+		..Default::default()
+	};
+
+	let qualifies = high_scores.qualifies(score.0);
+	*name_entry = NameEntry {
+		name: if qualifies { Some(String::new()) } else { None },
+		skip_typed: true,
+	};
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+				flex_direction: FlexDirection::ColumnReverse,
+				justify_content: JustifyContent::Center,
+				align_items: AlignItems::Center,
+				..Default::default()
+			},
+			material: materials.transparent.clone(),
+			..Default::default()
+		})
+		.insert(StateScoped(AppState::GameOver))
+		.with_children(|parent| {
+			parent.spawn_bundle(text(locale.get("game-over").to_string(), 64.0));
+			parent.spawn_bundle(text(
+				locale.format("hud-score", &[("score", &score.0)]),
+				40.0,
+			));
+			if qualifies {
+				parent.spawn_bundle(text(
+					locale.get("game-over-new-high-score").to_string(),
+					32.0,
+				));
+				parent
+					.spawn_bundle(text("_".to_string(), 40.0))
+					.insert(NameText);
+			}
+			parent.spawn_bundle(text(locale.get("game-over-continue").to_string(), 24.0));
+		});
+}
+
+/// Types the name for the high score table and leaves the game over screen on enter.
+#[allow(clippy::too_many_arguments)]
+fn enter_name(
+	kb_input: Res<Input<KeyCode>>,
+	gamepad_input: Res<Input<GamepadButton>>,
+	gamepad: Res<ActiveGamepad>,
+	mut characters: EventReader<ReceivedCharacter>,
+	locale: Res<Locale>,
+	score: Res<Score>,
+	mut name_entry: ResMut<NameEntry>,
+	mut high_scores: ResMut<HighScores>,
+	mut state: ResMut<State<AppState>>,
+	mut texts: Query<&mut Text, With<NameText>>,
+) {
+	let skip_typed = std::mem::take(&mut name_entry.skip_typed);
+	if let Some(name) = name_entry.name.as_mut() {
+		for character in characters.iter().filter(|_| !skip_typed) {
+			if !character.char.is_control() && name.chars().count() < MAX_NAME_LENGTH {
+				name.push(character.char);
+			}
+		}
+		if kb_input.just_pressed(KeyCode::Back) {
+			name.pop();
+		}
+		let value = format!("{}_", name);
+		for mut text in texts.iter_mut() {
+			if text.sections[0].value != value {
+				text.sections[0].value = value.clone();
+			}
+		}
+	}
+
+	let confirmed = kb_input.just_pressed(KeyCode::Return)
+		|| kb_input.just_pressed(KeyCode::NumpadEnter)
+		|| gamepad.0.map_or(false, |gamepad| {
+			gamepad_input.just_pressed(GamepadButton(gamepad, GamepadButtonType::South))
+		});
+	if !confirmed {
+		return;
+	}
+	if let Some(name) = name_entry.name.take() {
+		let name = match name.trim() {
+			"" => locale.get("high-scores-anonymous").to_string(),
+			name => name.to_string(),
+		};
+		info!("New high score {} by {}", score.0, name);
+		high_scores.insert(name, score.0);
+		if let Err(e) = high_scores.save() {
+			error!("Failed to save high scores to {}: {}", HIGH_SCORES_PATH, e);
+		}
+	}
+	if let Err(e) = state.set(AppState::HighScores) {
+		warn!("Can't show the high scores: {:?}", e);
+	}
+}
+
+fn setup_high_scores(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	locale: Res<Locale>,
+	button_materials: Res<ButtonMaterials>,
+	gamepad: Res<ActiveGamepad>,
+	mut focus: ResMut<MenuFocus>,
+	high_scores: Res<HighScores>,
+) {
+	let font = asset_server.load("FiraSans-Bold.ttf");
+	let mut table = vec![locale.get("high-scores-title").to_string(), String::new()];
+	if high_scores.0.is_empty() {
+		table.push(locale.get("high-scores-empty").to_string());
+	}
+	for (idx, HighScore { name, score }) in high_scores.0.iter().enumerate() {
+		table.push(locale.format(
+			"high-scores-entry",
+			&[("rank", &(idx + 1)), ("name", name), ("score", score)],
+		));
+	}
+
+	commands
+		.spawn_bundle(TextBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				position: Rect {
+					left: Val::Percent(10.0),
+					top: Val::Percent(5.0),
+					..Default::default()
+				},
+				..Default::default()
+			},
+			text: Text::with_section(
+				table.join("\n"),
+				TextStyle {
+					font,
+					font_size: 32.0,
+					color: Color::rgb(0.9, 0.9, 0.9),
+				},
+				TextAlignment::default(),
+			),
+			..Default::default()
+		})
+		.insert(StateScoped(AppState::HighScores));
+
+	let menu = spawn_menu(
+		&mut commands,
+		&asset_server,
+		&locale,
+		&button_materials,
+		&mut focus,
+		&gamepad,
+		&HIGH_SCORES_MENU,
+	);
+	commands
+		.entity(menu)
+		.insert(StateScoped(AppState::HighScores));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn high_scores_stay_sorted_and_short() {
+		let mut high_scores = HighScores::default();
+		assert!(!high_scores.qualifies(0));
+		for score in 1..=12 {
+			assert!(high_scores.qualifies(score));
+			high_scores.insert(format!("player {}", score), score);
+		}
+		let scores = high_scores
+			.0
+			.iter()
+			.map(|high_score| high_score.score)
+			.collect::<Vec<_>>();
+		assert_eq!(scores, (3..=12).rev().collect::<Vec<_>>());
+		assert!(!high_scores.qualifies(3));
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn equal_scores_keep_their_order() {
+		let mut high_scores = HighScores::default();
+		high_scores.insert("first".to_string(), 5);
+		high_scores.insert("second".to_string(), 5);
+		let names = high_scores
+			.0
+			.iter()
+			.map(|high_score| high_score.name.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(names, ["first", "second"]);
+	}
+}
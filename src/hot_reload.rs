@@ -0,0 +1,81 @@
+use crate::components::Level;
+use crate::hazards::HazardConfig;
+use crate::level::{LevelData, LevelHandles, LevelSpawner};
+use bevy::asset::HandleId;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+/// Reloads assets changed on disk while the game is running. Only does anything in debug builds.
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		if !cfg!(debug_assertions) {
+			return;
+		}
+		app.add_startup_system(watch_assets.system())
+			.add_system(refresh_materials.system())
+			.add_system(respawn_changed_level.system());
+	}
+}
+
+fn watch_assets(asset_server: Res<AssetServer>) {
+	match asset_server.watch_for_changes() {
+		Ok(()) => info!("Watching assets for changes"),
+		Err(e) => warn!("Can't watch assets for changes: {}", e),
+	}
+}
+
+/// Materials don't notice when their texture changes, so mark them as changed too.
+fn refresh_materials(
+	mut texture_events: EventReader<AssetEvent<Texture>>,
+	mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+	let modified = texture_events
+		.iter()
+		.filter_map(|event| match event {
+			AssetEvent::Modified { handle } => Some(handle.id),
+			_ => None,
+		})
+		.collect::<HashSet<_>>();
+	if modified.is_empty() {
+		return;
+	}
+
+	let using_modified = materials
+		.iter()
+		.filter(|(_, material)| {
+			material
+				.texture
+				.as_ref()
+				.map_or(false, |texture| modified.contains(&texture.id))
+		})
+		.map(|(id, _)| id)
+		.collect::<Vec<HandleId>>();
+	for id in using_modified {
+		materials.get_mut(id);
+	}
+}
+
+/// Respawns the current level when its file or the hazard tuning changes, leaving the player
+/// where they are.
+fn respawn_changed_level(
+	mut level_events: EventReader<AssetEvent<LevelData>>,
+	mut hazard_events: EventReader<AssetEvent<HazardConfig>>,
+	level: Res<Level>,
+	handles: Res<LevelHandles>,
+	mut spawner: ResMut<LevelSpawner>,
+) {
+	let current = handles.0.get(&level.l_type);
+	let level_changed = level_events.iter().any(|event| match event {
+		AssetEvent::Modified { handle } => Some(handle) == current,
+		_ => false,
+	});
+	let hazards_changed = hazard_events
+		.iter()
+		.any(|event| matches!(event, AssetEvent::Modified { .. }));
+	if level_changed || hazards_changed {
+		info!("Level {:?} changed, respawning it", level.l_type);
+		spawner.respawn();
+	}
+}
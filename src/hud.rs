@@ -0,0 +1,420 @@
+use crate::components::*;
+use crate::i18n::Locale;
+use crate::score::Combo;
+use crate::settings::Settings;
+use crate::ui::ActiveStateScoped;
+use crate::AppState;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+/// Window height the HUD sizes are designed for. The HUD scales relative to it.
+const REFERENCE_HEIGHT: f32 = 720.0;
+/// Share of the window width taken by the minimap.
+const MINIMAP_WIDTH: f32 = 0.2;
+const MINIMAP_ICON_SIZE: f32 = 5.0;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<HudMaterials>()
+			.add_system_set(SystemSet::on_enter(AppState::Game).with_system(setup_hud.system()))
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(scale_hud.system())
+					.with_system(update_health_bar.system())
+					.with_system(update_weapon.system())
+					.with_system(update_score.system())
+					.with_system(update_level_name.system())
+					.with_system(update_minimap.system()),
+			)
+			.add_system_set(SystemSet::on_resume(AppState::Game).with_system(setup_hud.system()));
+	}
+}
+
+pub struct HudMaterials {
+	transparent: Handle<ColorMaterial>,
+	background: Handle<ColorMaterial>,
+	/// Health bar color of every player.
+	health: [Handle<ColorMaterial>; MAX_PLAYERS],
+	cooldown: Handle<ColorMaterial>,
+	player_icon: Handle<ColorMaterial>,
+	portal_icon: Handle<ColorMaterial>,
+	spikes_icon: Handle<ColorMaterial>,
+	checkpoint_icon: Handle<ColorMaterial>,
+}
+
+impl FromWorld for HudMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		HudMaterials {
+			transparent: materials.add(Color::NONE.into()),
+			background: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.5).into()),
+			health: [
+				materials.add(Color::rgb(0.8, 0.15, 0.15).into()),
+				materials.add(Color::rgb(0.2, 0.45, 0.85).into()),
+			],
+			cooldown: materials.add(Color::rgb(0.9, 0.75, 0.2).into()),
+			player_icon: materials.add(Color::rgb(0.2, 0.9, 0.2).into()),
+			portal_icon: materials.add(Color::rgb(0.6, 0.3, 0.9).into()),
+			spikes_icon: materials.add(Color::rgb(0.9, 0.2, 0.2).into()),
+			checkpoint_icon: materials.add(Color::rgb(0.9, 0.9, 0.9).into()),
+		}
+	}
+}
+
+/// Text whose font size follows the window height.
+struct HudText {
+	base_size: f32,
+}
+
+/// Health bar of the player with this index.
+struct HealthBar(usize);
+
+struct WeaponText;
+
+struct CooldownBar;
+
+struct ScoreText;
+
+struct ComboText;
+
+struct LevelNameText;
+
+struct Minimap;
+
+/// Minimap icon of the given world entity.
+struct MinimapIcon(Entity);
+
+fn setup_hud(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	settings: Res<Settings>,
+	materials: Res<HudMaterials>,
+) {
+	let font = asset_server.load("FiraSans-Bold.ttf");
+	let text = |value: &str, base_size: f32| {
+		(
+			TextBundle {
+				text: Text::with_section(
+					value,
+					TextStyle {
+						font: font.clone(),
+						font_size: base_size,
+						color: Color::rgb(0.9, 0.9, 0.9),
+					},
+					TextAlignment::default(),
+				),
+				..Default::default()
+			},
+			HudText { base_size },
+		)
+	};
+	let bar = |width: f32, fill: Handle<ColorMaterial>| {
+		(
+			NodeBundle {
+				style: Style {
+					size: Size::new(Val::Percent(width), Val::Percent(2.5)),
+					..Default::default()
+				},
+				material: materials.background.clone(),
+				..Default::default()
+			},
+			NodeBundle {
+				style: Style {
+					size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+					..Default::default()
+				},
+				material: fill,
+				..Default::default()
+			},
+		)
+	};
+
+	// Health of every player and the first player's weapon in the bottom left corner.
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				position: Rect {
+					left: Val::Percent(2.0),
+					bottom: Val::Percent(2.0),
+					..Default::default()
+				},
+				size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+				flex_direction: FlexDirection::ColumnReverse,
+				justify_content: JustifyContent::FlexEnd,
+				..Default::default()
+			},
+			material: materials.transparent.clone(),
+			..Default::default()
+		})
+		.insert(ActiveStateScoped(AppState::Game))
+		.with_children(|parent| {
+			for (idx, health) in materials.health.iter().enumerate().take(settings.players) {
+				let (health_background, health_fill) = bar(25.0, health.clone());
+				parent
+					.spawn_bundle(health_background)
+					.with_children(|parent| {
+						parent.spawn_bundle(health_fill).insert(HealthBar(idx));
+					});
+			}
+			let (weapon_text, weapon_scale) = text("", 24.0);
+			parent
+				.spawn_bundle(weapon_text)
+				.insert(weapon_scale)
+				.insert(WeaponText);
+			let (cooldown_background, cooldown_fill) = bar(10.0, materials.cooldown.clone());
+			parent
+				.spawn_bundle(cooldown_background)
+				.with_children(|parent| {
+					parent.spawn_bundle(cooldown_fill).insert(CooldownBar);
+				});
+		});
+
+	// Level name, score and combo in the top right corner.
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				position: Rect {
+					right: Val::Percent(2.0),
+					top: Val::Percent(2.0),
+					..Default::default()
+				},
+				flex_direction: FlexDirection::ColumnReverse,
+				align_items: AlignItems::FlexEnd,
+				..Default::default()
+			},
+			material: materials.transparent.clone(),
+			..Default::default()
+		})
+		.insert(ActiveStateScoped(AppState::Game))
+		.with_children(|parent| {
+			let (level_text, level_scale) = text("", 32.0);
+			parent
+				.spawn_bundle(level_text)
+				.insert(level_scale)
+				.insert(LevelNameText);
+			let (score_text, score_scale) = text("", 24.0);
+			parent
+				.spawn_bundle(score_text)
+				.insert(score_scale)
+				.insert(ScoreText);
+			let (combo_text, combo_scale) = text("", 24.0);
+			parent
+				.spawn_bundle(combo_text)
+				.insert(combo_scale)
+				.insert(ComboText);
+		});
+
+	// Minimap in the bottom right corner. Its size is set in `scale_hud`.
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				position: Rect {
+					right: Val::Percent(2.0),
+					bottom: Val::Percent(2.0),
+					..Default::default()
+				},
+				..Default::default()
+			},
+			material: materials.background.clone(),
+			..Default::default()
+		})
+		.insert(ActiveStateScoped(AppState::Game))
+		.insert(Minimap);
+}
+
+fn scale_hud(
+	windows: Res<Windows>,
+	level: Res<Level>,
+	mut texts: Query<(&mut Text, &HudText)>,
+	mut minimap: Query<&mut Style, With<Minimap>>,
+) {
+	let window = match windows.get_primary() {
+		Some(window) => window,
+		None => return,
+	};
+	let scale = window.height() / REFERENCE_HEIGHT;
+	for (mut text, hud_text) in texts.iter_mut() {
+		let font_size = (hud_text.base_size * scale).round();
+		if text.sections[0].style.font_size != font_size {
+			text.sections[0].style.font_size = font_size;
+		}
+	}
+
+	let width = (window.width() * MINIMAP_WIDTH).round();
+	let size = Size::new(
+		Val::Px(width),
+		Val::Px((width * level.size.y / level.size.x).round()),
+	);
+	for mut style in minimap.iter_mut() {
+		if style.size != size {
+			style.size = size;
+		}
+	}
+}
+
+fn update_health_bar(
+	players: Query<(&Player, &Health)>,
+	mut bars: Query<(&HealthBar, &mut Style)>,
+) {
+	for (HealthBar(idx), mut style) in bars.iter_mut() {
+		// Empty once the player is out.
+		let health = players
+			.iter()
+			.find(|(Player(player), _)| player == idx)
+			.map_or(0.0, |(_, health)| health.current as f32 / health.max as f32);
+		let width = Val::Percent(100.0 * health);
+		if style.size.width != width {
+			style.size.width = width;
+		}
+	}
+}
+
+fn update_weapon(
+	locale: Res<Locale>,
+	players: Query<(&Player, &Weapon)>,
+	mut texts: Query<&mut Text, With<WeaponText>>,
+	mut bars: Query<&mut Style, With<CooldownBar>>,
+) {
+	for (_, weapon) in players.iter().filter(|(Player(player), _)| *player == 0) {
+		for mut text in texts.iter_mut() {
+			let name = locale.get(weapon.name);
+			if text.sections[0].value != name {
+				text.sections[0].value = name.to_string();
+			}
+		}
+		let ready = 1.0 - weapon.cooldown_left / weapon.cooldown.max(f32::EPSILON);
+		let width = Val::Percent(100.0 * ready);
+		for mut style in bars.iter_mut() {
+			if style.size.width != width {
+				style.size.width = width;
+			}
+		}
+	}
+}
+
+fn update_score(
+	locale: Res<Locale>,
+	score: Res<Score>,
+	combo: Res<Combo>,
+	mut score_texts: Query<&mut Text, (With<ScoreText>, Without<ComboText>)>,
+	mut combo_texts: Query<&mut Text, With<ComboText>>,
+) {
+	for mut text in score_texts.iter_mut() {
+		let value = locale.format("hud-score", &[("score", &score.0)]);
+		if text.sections[0].value != value {
+			text.sections[0].value = value;
+		}
+	}
+	// Hidden until there's a combo going.
+	let value = if combo.multiplier > 1 {
+		locale.format("hud-combo", &[("combo", &combo.multiplier)])
+	} else {
+		String::new()
+	};
+	for mut text in combo_texts.iter_mut() {
+		if text.sections[0].value != value {
+			text.sections[0].value = value.clone();
+		}
+	}
+}
+
+fn update_level_name(
+	locale: Res<Locale>,
+	level: Res<Level>,
+	mut texts: Query<&mut Text, With<LevelNameText>>,
+) {
+	for mut text in texts.iter_mut() {
+		let value = locale.get(level.l_type.name_key());
+		if text.sections[0].value != value {
+			text.sections[0].value = value.to_string();
+		}
+	}
+}
+
+#[allow(clippy::type_complexity)]
+fn update_minimap(
+	mut commands: Commands,
+	level: Res<Level>,
+	materials: Res<HudMaterials>,
+	minimap: Query<Entity, With<Minimap>>,
+	tracked: Query<
+		(
+			Entity,
+			&Transform,
+			Option<&Player>,
+			Option<&PortalDestination>,
+			Option<&Spikes>,
+			Option<&Enemy>,
+		),
+		Or<(
+			With<Player>,
+			With<PortalDestination>,
+			With<Spikes>,
+			With<Enemy>,
+			With<Checkpoint>,
+		)>,
+	>,
+	mut icons: Query<(Entity, &MinimapIcon, &mut Style)>,
+) {
+	let minimap = match minimap.iter().next() {
+		Some(minimap) => minimap,
+		None => return,
+	};
+	let (min, max) = level.bounds();
+	let to_minimap = |translation: Vec3| {
+		let relative = (translation.truncate() - min) / (max - min) * 100.0;
+		Rect {
+			left: Val::Percent(relative.x),
+			bottom: Val::Percent(relative.y),
+			..Default::default()
+		}
+	};
+
+	let mut has_icon = HashSet::default();
+	for (icon, MinimapIcon(target), mut style) in icons.iter_mut() {
+		match tracked.get(*target) {
+			Ok((_, transform, ..)) => {
+				let position = to_minimap(transform.translation);
+				if style.position != position {
+					style.position = position;
+				}
+				has_icon.insert(*target);
+			}
+			Err(_) => commands.entity(icon).despawn_recursive(),
+		}
+	}
+
+	for (entity, transform, player, portal, spikes, enemy) in tracked.iter() {
+		if has_icon.contains(&entity) {
+			continue;
+		}
+		let material = if player.is_some() {
+			materials.player_icon.clone()
+		} else if portal.is_some() {
+			materials.portal_icon.clone()
+		} else if spikes.is_some() || enemy.is_some() {
+			materials.spikes_icon.clone()
+		} else {
+			materials.checkpoint_icon.clone()
+		};
+		commands.entity(minimap).with_children(|parent| {
+			parent
+				.spawn_bundle(NodeBundle {
+					style: Style {
+						position_type: PositionType::Absolute,
+						position: to_minimap(transform.translation),
+						size: Size::new(Val::Px(MINIMAP_ICON_SIZE), Val::Px(MINIMAP_ICON_SIZE)),
+						..Default::default()
+					},
+					material,
+					..Default::default()
+				})
+				.insert(MinimapIcon(entity));
+		});
+	}
+}
@@ -0,0 +1,214 @@
+use crate::console::{ConsoleCommand, ConsoleEvent};
+use crate::settings::Settings;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+static LANGUAGE_COMMAND: &str = "language";
+
+pub struct I18nPlugin;
+
+impl Plugin for I18nPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_asset::<StringTable>()
+			.init_asset_loader::<StringTableLoader>()
+			.init_resource::<Locale>()
+			.add_system(language_command.system())
+			.add_system(update_locale.system().label(LocaleLabel))
+			.add_system(localize_texts.system().after(LocaleLabel));
+	}
+}
+
+/// Systems reading [`Locale`] should run after this label to see a language switch immediately.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LocaleLabel;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+	English,
+	Polish,
+}
+
+impl Language {
+	pub const ALL: [Language; 2] = [Language::English, Language::Polish];
+
+	pub fn code(self) -> &'static str {
+		match self {
+			Language::English => "en",
+			Language::Polish => "pl",
+		}
+	}
+
+	/// Path of the string table, relative to the assets directory.
+	pub fn file_name(self) -> String {
+		format!("i18n/{}.lang", self.code())
+	}
+}
+
+impl Default for Language {
+	fn default() -> Self {
+		Language::English
+	}
+}
+
+impl std::str::FromStr for Language {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Language::ALL
+			.iter()
+			.copied()
+			.find(|language| language.code() == s)
+			.ok_or_else(|| "expected one of en, pl".to_string())
+	}
+}
+
+impl fmt::Display for Language {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.code())
+	}
+}
+
+/// Translated strings by key, loaded from `i18n/<code>.lang`.
+#[derive(Debug, TypeUuid)]
+#[uuid = "4a8d2c6e-1f37-4b95-8e0c-3d7a9b5f1e26"]
+pub struct StringTable(pub HashMap<String, String>);
+
+#[derive(Default)]
+pub struct StringTableLoader;
+
+impl AssetLoader for StringTableLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let strings = ron::de::from_bytes::<HashMap<String, String>>(bytes)?;
+			load_context.set_default_asset(LoadedAsset::new(StringTable(strings)));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["lang"]
+	}
+}
+
+/// Strings of the language picked in [`Settings`].
+pub struct Locale {
+	pub language: Language,
+	tables: HashMap<Language, Handle<StringTable>>,
+	strings: HashMap<String, String>,
+	/// Used for keys missing in the current language.
+	fallback: HashMap<String, String>,
+}
+
+impl FromWorld for Locale {
+	fn from_world(world: &mut World) -> Self {
+		let asset_server = world.get_resource::<AssetServer>().unwrap();
+		let tables = Language::ALL
+			.iter()
+			.map(|language| (*language, asset_server.load(language.file_name().as_str())))
+			.collect();
+		Locale {
+			language: Language::default(),
+			tables,
+			strings: HashMap::default(),
+			fallback: HashMap::default(),
+		}
+	}
+}
+
+impl Locale {
+	/// Returns the translation of `key`, or the key itself if no language has it.
+	pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+		self.strings
+			.get(key)
+			.or_else(|| self.fallback.get(key))
+			.map_or(key, String::as_str)
+	}
+
+	/// Like [`Locale::get`], with every `{name}` replaced by the value of `name` in `args`.
+	pub fn format(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+		let mut text = self.get(key).to_string();
+		for (name, value) in args {
+			text = text.replace(&format!("{{{}}}", name), &value.to_string());
+		}
+		text
+	}
+}
+
+/// Text whose first section shows the translation of this key.
+pub struct Localized(pub &'static str);
+
+fn language_command(
+	mut commands: EventReader<ConsoleCommand>,
+	mut console_events: EventWriter<ConsoleEvent>,
+	mut settings: ResMut<Settings>,
+) {
+	for ConsoleCommand(command) in commands.iter() {
+		let mut words = command.split_whitespace();
+		if words.next() != Some(LANGUAGE_COMMAND) {
+			continue;
+		}
+		match words.next().map(str::parse::<Language>) {
+			Some(Ok(language)) => settings.language = language,
+			Some(Err(e)) => console_events.send(ConsoleEvent::Log(format!("{}\n", e))),
+			None => console_events.send(ConsoleEvent::Log(format!("{}\n", settings.language))),
+		}
+	}
+}
+
+/// Picks the strings of the language in [`Settings`], again whenever a string table is reloaded.
+fn update_locale(
+	mut table_events: EventReader<AssetEvent<StringTable>>,
+	settings: Res<Settings>,
+	tables: Res<Assets<StringTable>>,
+	mut locale: ResMut<Locale>,
+	mut windows: ResMut<Windows>,
+) {
+	let tables_changed = table_events.iter().count() > 0;
+	if !tables_changed && !settings.is_changed() {
+		return;
+	}
+
+	let table = |language: Language| {
+		locale
+			.tables
+			.get(&language)
+			.and_then(|handle| tables.get(handle))
+			.map(|table| table.0.clone())
+			.unwrap_or_default()
+	};
+	let strings = table(settings.language);
+	let fallback = table(Language::default());
+	if locale.language != settings.language {
+		info!("Switching language to {}", settings.language);
+	}
+	locale.language = settings.language;
+	locale.strings = strings;
+	locale.fallback = fallback;
+
+	if let Some(window) = windows.get_primary_mut() {
+		window.set_title(locale.get("game-name").to_string());
+	}
+}
+
+fn localize_texts(
+	locale: Res<Locale>,
+	mut texts: Query<(&mut Text, &Localized, ChangeTrackers<Localized>)>,
+) {
+	for (mut text, localized, trackers) in texts.iter_mut() {
+		if !locale.is_changed() && !trackers.is_changed() {
+			continue;
+		}
+		let value = locale.get(localized.0);
+		if text.sections[0].value != value {
+			text.sections[0].value = value.to_string();
+		}
+	}
+}
@@ -0,0 +1,635 @@
+use crate::checkpoint::{spawn_checkpoint, RespawnPoint};
+use crate::cli::Args;
+use crate::components::*;
+use crate::dungeon::Dungeon;
+use crate::hazards::*;
+use crate::score::ScoreEvent;
+use crate::stats::StatsEvent;
+use crate::AppState;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Height of level entities above the floor tiles.
+pub const ENTITY_Z: f32 = 0.1;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		let seed = app
+			.world()
+			.get_resource::<Args>()
+			.and_then(|args| args.seed)
+			.unwrap_or_else(|| {
+				use std::hash::{BuildHasher, Hasher};
+				std::collections::hash_map::RandomState::new()
+					.build_hasher()
+					.finish()
+			});
+		info!("Dungeon seed {}", seed);
+
+		app.add_asset::<LevelData>()
+			.init_asset_loader::<LevelDataLoader>()
+			.init_resource::<LevelSpawner>()
+			.insert_resource(Dungeon { seed, depth: 0 })
+			.add_event::<TravelEvent>()
+			.add_startup_system(load_levels.system())
+			.add_system_set(
+				SystemSet::on_enter(AppState::Game).with_system(place_player_on_spawn.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(travel.system())
+					.with_system(spawn_loaded_level.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Editor).with_system(spawn_loaded_level.system()),
+			);
+	}
+}
+
+/// A floor tile, stored in level files as a single character.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileKind {
+	Ground,
+	/// Collides like the wall around the level.
+	TransparentGround,
+	Water,
+	Bridge,
+	Conveyor(ConveyorDirection),
+}
+
+/// Where a conveyor tile pushes the player.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConveyorDirection {
+	Up,
+	Down,
+	Left,
+	Right,
+}
+
+impl ConveyorDirection {
+	pub const ALL: [ConveyorDirection; 4] = [
+		ConveyorDirection::Right,
+		ConveyorDirection::Down,
+		ConveyorDirection::Left,
+		ConveyorDirection::Up,
+	];
+
+	pub fn vector(self) -> Vec2 {
+		match self {
+			ConveyorDirection::Up => Vec2::Y,
+			ConveyorDirection::Down => -Vec2::Y,
+			ConveyorDirection::Left => -Vec2::X,
+			ConveyorDirection::Right => Vec2::X,
+		}
+	}
+}
+
+impl fmt::Display for ConveyorDirection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			ConveyorDirection::Up => "up",
+			ConveyorDirection::Down => "down",
+			ConveyorDirection::Left => "left",
+			ConveyorDirection::Right => "right",
+		};
+		f.write_str(name)
+	}
+}
+
+impl TileKind {
+	const EMPTY: char = '.';
+
+	fn from_char(c: char) -> Option<Self> {
+		match c {
+			'#' => Some(TileKind::Ground),
+			'+' => Some(TileKind::TransparentGround),
+			'~' => Some(TileKind::Water),
+			'=' => Some(TileKind::Bridge),
+			'^' => Some(TileKind::Conveyor(ConveyorDirection::Up)),
+			'v' => Some(TileKind::Conveyor(ConveyorDirection::Down)),
+			'<' => Some(TileKind::Conveyor(ConveyorDirection::Left)),
+			'>' => Some(TileKind::Conveyor(ConveyorDirection::Right)),
+			_ => None,
+		}
+	}
+
+	pub fn is_walkable(self) -> bool {
+		self != TileKind::TransparentGround
+	}
+
+	pub fn to_char(self) -> char {
+		match self {
+			TileKind::Ground => '#',
+			TileKind::TransparentGround => '+',
+			TileKind::Water => '~',
+			TileKind::Bridge => '=',
+			TileKind::Conveyor(ConveyorDirection::Up) => '^',
+			TileKind::Conveyor(ConveyorDirection::Down) => 'v',
+			TileKind::Conveyor(ConveyorDirection::Left) => '<',
+			TileKind::Conveyor(ConveyorDirection::Right) => '>',
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EntityKind {
+	Spikes,
+	/// Spikes that go in and out of the ground. Neighbouring ones alternate.
+	TimedSpikes,
+	Portal(LevelType),
+	Enemy,
+	/// The active checkpoint is where the player starts.
+	Checkpoint {
+		active: bool,
+	},
+	/// Moves along the longest straight line of water it's placed on.
+	Platform,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityData {
+	/// Tile coordinates.
+	pub position: (i32, i32),
+	pub kind: EntityKind,
+}
+
+/// Layout of a level, loaded from a `.level` file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "3c1f6e0a-8d42-4b7e-a5c9-0e2b9d7f4a61"]
+pub struct LevelData {
+	/// Rows of floor tiles from top to bottom, one character per tile: `#` ground, `+` transparent
+	/// ground, `~` water, `=` bridge and `.` nothing.
+	pub tiles: Vec<String>,
+	pub entities: Vec<EntityData>,
+}
+
+impl LevelData {
+	pub fn columns(&self) -> usize {
+		self.tiles.iter().map(|row| row.len()).max().unwrap_or(0)
+	}
+
+	pub fn rows(&self) -> usize {
+		self.tiles.len()
+	}
+
+	pub fn size(&self) -> Vec2 {
+		Vec2::new(self.columns() as f32, self.rows() as f32) * TILE_SIZE
+	}
+
+	/// Converts tile coordinates to the row and column in [`LevelData::tiles`].
+	fn tile_index(&self, (x, y): (i32, i32)) -> Option<(usize, usize)> {
+		let (columns, rows) = (self.columns() as i32, self.rows() as i32);
+		let column = x + columns / 2;
+		let row = rows - rows / 2 - 1 - y;
+		if (0..columns).contains(&column) && (0..rows).contains(&row) {
+			Some((row as usize, column as usize))
+		} else {
+			None
+		}
+	}
+
+	/// Converts a row and a column in [`LevelData::tiles`] to tile coordinates.
+	pub fn tile_position(&self, row: usize, column: usize) -> (i32, i32) {
+		let (columns, rows) = (self.columns() as i32, self.rows() as i32);
+		(
+			column as i32 - columns / 2,
+			rows - rows / 2 - 1 - row as i32,
+		)
+	}
+
+	pub fn contains(&self, position: (i32, i32)) -> bool {
+		self.tile_index(position).is_some()
+	}
+
+	/// Ends of the longest horizontal or vertical line of water tiles going through `position`.
+	pub fn water_line(&self, position: (i32, i32)) -> ((i32, i32), (i32, i32)) {
+		let end = |(dx, dy): (i32, i32)| {
+			let mut end = position;
+			while self.tile((end.0 + dx, end.1 + dy)) == Some(TileKind::Water) {
+				end = (end.0 + dx, end.1 + dy);
+			}
+			end
+		};
+		let horizontal = (end((-1, 0)), end((1, 0)));
+		let vertical = (end((0, -1)), end((0, 1)));
+		let length = |(from, to): ((i32, i32), (i32, i32))| (to.0 - from.0) + (to.1 - from.1);
+		if length(vertical) > length(horizontal) {
+			vertical
+		} else {
+			horizontal
+		}
+	}
+
+	pub fn tile(&self, position: (i32, i32)) -> Option<TileKind> {
+		let (row, column) = self.tile_index(position)?;
+		self.tiles[row]
+			.chars()
+			.nth(column)
+			.and_then(TileKind::from_char)
+	}
+
+	/// Changes the tile at `position`. Returns whether anything changed.
+	pub fn set_tile(&mut self, position: (i32, i32), tile: Option<TileKind>) -> bool {
+		let (row, column) = match self.tile_index(position) {
+			Some(index) => index,
+			None => return false,
+		};
+		if self.tile(position) == tile {
+			return false;
+		}
+		let columns = self.columns();
+		let mut chars = self.tiles[row].chars().collect::<Vec<_>>();
+		chars.resize(columns, TileKind::EMPTY);
+		chars[column] = tile.map_or(TileKind::EMPTY, TileKind::to_char);
+		self.tiles[row] = chars.into_iter().collect();
+		true
+	}
+
+	/// Whether the player can stand at `position` in the world coordinates.
+	pub fn is_walkable(&self, position: Vec2) -> bool {
+		self.tile(world_to_tile(position))
+			.map_or(false, TileKind::is_walkable)
+	}
+
+	pub fn entity(&self, position: (i32, i32)) -> Option<&EntityData> {
+		self.entities
+			.iter()
+			.find(|entity| entity.position == position)
+	}
+
+	/// Replaces the entity at `position`. Returns whether anything changed.
+	pub fn set_entity(&mut self, position: (i32, i32), kind: Option<EntityKind>) -> bool {
+		if self.entity(position).map(|entity| entity.kind) == kind || !self.contains(position) {
+			return false;
+		}
+		self.entities.retain(|entity| entity.position != position);
+		if let Some(kind) = kind {
+			// There is only one place to start from.
+			if kind == (EntityKind::Checkpoint { active: true }) {
+				for entity in self.entities.iter_mut() {
+					if let EntityKind::Checkpoint { active } = &mut entity.kind {
+						*active = false;
+					}
+				}
+			}
+			self.entities.push(EntityData { position, kind });
+		}
+		true
+	}
+
+	/// Position of the active checkpoint in the world coordinates.
+	pub fn start_position(&self) -> Option<Vec2> {
+		self.entities
+			.iter()
+			.find(|entity| entity.kind == EntityKind::Checkpoint { active: true })
+			.map(|entity| tile_to_world(entity.position))
+	}
+}
+
+pub fn tile_to_world((x, y): (i32, i32)) -> Vec2 {
+	Vec2::new(x as f32, y as f32) * TILE_SIZE
+}
+
+pub fn world_to_tile(position: Vec2) -> (i32, i32) {
+	let tile = (position / TILE_SIZE).round();
+	(tile.x as i32, tile.y as i32)
+}
+
+#[derive(Default)]
+pub struct LevelDataLoader;
+
+impl AssetLoader for LevelDataLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let level = ron::de::from_bytes::<LevelData>(bytes)?;
+			load_context.set_default_asset(LoadedAsset::new(level));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["level"]
+	}
+}
+
+/// Handles of all level files, loaded at startup.
+pub struct LevelHandles(pub HashMap<LevelType, Handle<LevelData>>);
+
+impl LevelHandles {
+	pub fn get<'a>(
+		&self,
+		l_type: LevelType,
+		levels: &'a Assets<LevelData>,
+	) -> Option<&'a LevelData> {
+		self.0.get(&l_type).and_then(|handle| levels.get(handle))
+	}
+}
+
+/// Moves the player to the start of another level.
+#[derive(Debug)]
+pub struct TravelEvent(pub LevelType);
+
+/// Respawns the level entities once the level file of the current [`Level`] is loaded.
+#[derive(Default)]
+pub struct LevelSpawner {
+	pending: bool,
+	/// Whether to move the player to the start of the level after spawning it.
+	place_player: bool,
+}
+
+impl LevelSpawner {
+	/// Despawns the current level entities and spawns them again from the level file.
+	pub fn respawn(&mut self) {
+		self.pending = true;
+	}
+
+	/// Like [`LevelSpawner::respawn`], and moves the players to the start of the level.
+	pub fn enter(&mut self) {
+		self.pending = true;
+		self.place_player = true;
+	}
+}
+
+/// Marks everything spawned from a level file.
+pub struct LevelEntity;
+
+fn load_levels(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	dungeon: Res<Dungeon>,
+	mut levels: ResMut<Assets<LevelData>>,
+) {
+	let handles = LevelType::ALL
+		.iter()
+		.map(|l_type| {
+			let handle = match l_type.file_name() {
+				Some(file_name) => asset_server.load(file_name),
+				None => levels.add(dungeon.generate()),
+			};
+			(*l_type, handle)
+		})
+		.collect();
+	commands.insert_resource(LevelHandles(handles));
+}
+
+fn travel(
+	mut events: EventReader<TravelEvent>,
+	mut level: ResMut<Level>,
+	mut spawner: ResMut<LevelSpawner>,
+	mut dungeon: ResMut<Dungeon>,
+	handles: Res<LevelHandles>,
+	mut levels: ResMut<Assets<LevelData>>,
+	mut score_events: EventWriter<ScoreEvent>,
+	mut stats_events: EventWriter<StatsEvent>,
+) {
+	let destination = match events.iter().last() {
+		Some(TravelEvent(destination)) => *destination,
+		None => return,
+	};
+	// Portals keep sending events until the player is moved away from them.
+	if spawner.pending {
+		return;
+	}
+
+	if destination == LevelType::Secret1 {
+		// Every portal inside the dungeon leads one floor deeper.
+		dungeon.depth = if level.l_type == LevelType::Secret1 {
+			dungeon.depth + 1
+		} else {
+			0
+		};
+		if let Some(handle) = handles.0.get(&LevelType::Secret1) {
+			levels.set(handle.clone(), dungeon.generate());
+		}
+		info!("Entering dungeon floor {}", dungeon.depth);
+	}
+	if level.l_type != LevelType::Hub {
+		score_events.send(ScoreEvent::LevelCleared);
+	}
+	stats_events.send(StatsEvent::PortalUsed(destination));
+	info!("Travelling from {:?} to {:?}", level.l_type, destination);
+	level.l_type = destination;
+	spawner.enter();
+}
+
+fn place_player_on_spawn(mut spawner: ResMut<LevelSpawner>) {
+	spawner.enter();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_loaded_level(
+	mut commands: Commands,
+	mut spawner: ResMut<LevelSpawner>,
+	mut level: ResMut<Level>,
+	mut respawn_point: ResMut<RespawnPoint>,
+	handles: Res<LevelHandles>,
+	levels: Res<Assets<LevelData>>,
+	materials: Res<Textures>,
+	hazard_materials: Res<HazardMaterials>,
+	hazard_configs: Res<Assets<HazardConfig>>,
+	hazard_config: Res<HazardConfigHandle>,
+	level_entities: Query<Entity, With<LevelEntity>>,
+	mut players: Query<&mut Transform, With<Player>>,
+) {
+	if !spawner.pending {
+		return;
+	}
+	let data = match handles.get(level.l_type, &levels) {
+		Some(data) => data,
+		None => return,
+	};
+
+	for entity in level_entities.iter() {
+		commands.entity(entity).despawn_recursive();
+	}
+	level.size = data.size();
+	let hazards = hazard_config.get(&hazard_configs);
+	spawn_level(
+		&mut commands,
+		&materials,
+		&hazard_materials,
+		&hazards,
+		&level,
+		data,
+	);
+	info!("Spawned level {:?}", level.l_type);
+
+	if spawner.place_player {
+		respawn_point.0 = data.start_position().unwrap_or(Vec2::ZERO);
+		for mut player in players.iter_mut() {
+			player.translation.x = respawn_point.0.x;
+			player.translation.y = respawn_point.0.y;
+		}
+	}
+	spawner.pending = false;
+	spawner.place_player = false;
+}
+
+fn spawn_level(
+	commands: &mut Commands,
+	materials: &Textures,
+	hazard_materials: &HazardMaterials,
+	hazards: &HazardConfig,
+	level: &Level,
+	data: &LevelData,
+) {
+	for (row, tiles) in data.tiles.iter().enumerate() {
+		for (column, c) in tiles.chars().enumerate() {
+			let tile = match TileKind::from_char(c) {
+				Some(tile) => tile,
+				None => continue,
+			};
+			let position = data.tile_position(row, column);
+			let material = match tile {
+				TileKind::Ground => materials.ground_tile.clone(),
+				TileKind::TransparentGround => materials.transparent_ground_tile.clone(),
+				TileKind::Water => materials.water_tile.clone(),
+				TileKind::Bridge => materials.bridge_tile.clone(),
+				TileKind::Conveyor(_) => materials.conveyor_tile.clone(),
+			};
+			let mut transform = Transform::from_translation(tile_to_world(position).extend(0.0));
+			if let TileKind::Conveyor(direction) = tile {
+				// The conveyor texture points right.
+				let direction = direction.vector();
+				transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+			}
+			let mut entity = commands.spawn_bundle(SpriteBundle {
+				material,
+				transform,
+				..Default::default()
+			});
+			entity.insert(LevelEntity);
+			match tile {
+				TileKind::TransparentGround => {
+					entity.insert(Collidable);
+				}
+				TileKind::Water => {
+					entity
+						.insert(SlowsPlayer(hazards.water_speed))
+						.insert(Water);
+				}
+				TileKind::Conveyor(direction) => {
+					entity.insert(Conveyor(direction.vector() * hazards.conveyor_speed));
+				}
+				TileKind::Ground | TileKind::Bridge => {}
+			}
+		}
+	}
+
+	// Wall around the level.
+	let (columns, rows) = (level.tile_columns(), level.tile_rows());
+	for y in rows.start - 1..=rows.end {
+		for x in columns.start - 1..=columns.end {
+			if columns.contains(&x) && rows.contains(&y) {
+				continue;
+			}
+			commands
+				.spawn_bundle(SpriteBundle {
+					material: materials.transparent_ground_tile.clone(),
+					transform: Transform::from_translation(tile_to_world((x, y)).extend(0.0)),
+					..Default::default()
+				})
+				.insert(Collidable)
+				.insert(LevelEntity);
+		}
+	}
+
+	for entity in data.entities.iter() {
+		let position = tile_to_world(entity.position);
+		match entity.kind {
+			EntityKind::Spikes => {
+				commands
+					.spawn_bundle(SpriteBundle {
+						material: materials.spikes_texture.clone(),
+						transform: Transform::from_translation(position.extend(ENTITY_Z)),
+						..Default::default()
+					})
+					.insert(Spikes)
+					.insert(Hazard::new(hazards.spikes_damage))
+					.insert(Collidable)
+					.insert(LevelEntity);
+			}
+			EntityKind::TimedSpikes => {
+				let (x, y) = entity.position;
+				let extended = (x + y).rem_euclid(2) == 0;
+				commands
+					.spawn_bundle(SpriteBundle {
+						material: if extended {
+							materials.spikes_texture.clone()
+						} else {
+							hazard_materials.retracted_spikes.clone()
+						},
+						transform: Transform::from_translation(position.extend(ENTITY_Z)),
+						..Default::default()
+					})
+					.insert(Spikes)
+					.insert(Hazard {
+						damage: hazards.timed_spikes_damage,
+						active: extended,
+					})
+					.insert(RetractingSpikes::new(
+						hazards.timed_spikes_extended,
+						hazards.timed_spikes_retracted,
+						extended,
+					))
+					.insert(Collidable)
+					.insert(LevelEntity);
+			}
+			EntityKind::Portal(destination) => {
+				commands
+					.spawn_bundle(SpriteBundle {
+						material: materials.portal_texture.clone(),
+						transform: Transform::from_translation(position.extend(ENTITY_Z)),
+						..Default::default()
+					})
+					.insert(PortalDestination(destination))
+					.insert(Collidable)
+					.insert(LevelEntity);
+			}
+			EntityKind::Enemy => {
+				commands
+					.spawn_bundle(SpriteBundle {
+						material: materials.enemy_texture.clone(),
+						// The bird texture is much bigger than a tile.
+						sprite: Sprite::new(Vec2::splat(TILE_SIZE)),
+						transform: Transform::from_translation(position.extend(ENTITY_Z)),
+						..Default::default()
+					})
+					.insert(Enemy)
+					.insert(Hazard::new(hazards.enemy_damage))
+					.insert(Collidable)
+					.insert(LevelEntity);
+			}
+			EntityKind::Checkpoint { active } => {
+				let checkpoint = spawn_checkpoint(commands, materials, position, active);
+				commands.entity(checkpoint).insert(LevelEntity);
+			}
+			EntityKind::Platform => {
+				let (from, to) = data.water_line(entity.position);
+				commands
+					.spawn_bundle(SpriteBundle {
+						material: materials.bridge_tile.clone(),
+						// Between the floor and the entities standing on it.
+						transform: Transform::from_translation(position.extend(ENTITY_Z / 2.0)),
+						..Default::default()
+					})
+					.insert(MovingPlatform::new(
+						tile_to_world(from),
+						tile_to_world(to),
+						hazards.platform_speed,
+					))
+					.insert(LevelEntity);
+			}
+		}
+	}
+}
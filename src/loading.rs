@@ -0,0 +1,256 @@
+use crate::cli::Args;
+use crate::ui::StateScoped;
+use crate::AppState;
+use bevy::asset::{AssetLoader, LoadContext, LoadState, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+static MANIFEST_PATH: &str = "assets.manifest";
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_asset::<AssetManifest>()
+			.init_asset_loader::<AssetManifestLoader>()
+			.init_resource::<LoadingMaterials>()
+			.init_resource::<LoadingAssets>()
+			.add_system_set(
+				SystemSet::on_enter(AppState::Loading).with_system(setup_loading.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Loading)
+					.with_system(load_manifest_assets.system())
+					.with_system(update_loading.system()),
+			);
+	}
+}
+
+/// Everything the game needs before it leaves [`AppState::Loading`], loaded from
+/// `assets.manifest`.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "e2c9a7f4-3b15-4d86-9f0a-71c8d5e6b243"]
+pub struct AssetManifest {
+	pub textures: Vec<String>,
+	pub fonts: Vec<String>,
+	pub audio: Vec<String>,
+	pub levels: Vec<String>,
+	pub configs: Vec<String>,
+	/// String tables of every language.
+	pub strings: Vec<String>,
+}
+
+impl AssetManifest {
+	fn paths(&self) -> impl Iterator<Item = &String> {
+		self.textures
+			.iter()
+			.chain(self.fonts.iter())
+			.chain(self.audio.iter())
+			.chain(self.levels.iter())
+			.chain(self.configs.iter())
+			.chain(self.strings.iter())
+	}
+}
+
+#[derive(Default)]
+pub struct AssetManifestLoader;
+
+impl AssetLoader for AssetManifestLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let manifest = ron::de::from_bytes::<AssetManifest>(bytes)?;
+			load_context.set_default_asset(LoadedAsset::new(manifest));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["manifest"]
+	}
+}
+
+/// Handles of the manifest and everything listed in it. Kept for the whole game, so that nothing
+/// gets unloaded when the last entity using it goes away.
+struct LoadingAssets {
+	manifest: Handle<AssetManifest>,
+	/// Empty until the manifest is loaded.
+	assets: Vec<(String, HandleUntyped)>,
+	manifest_loaded: bool,
+}
+
+impl FromWorld for LoadingAssets {
+	fn from_world(world: &mut World) -> Self {
+		let asset_server = world.get_resource::<AssetServer>().unwrap();
+		LoadingAssets {
+			manifest: asset_server.load(MANIFEST_PATH),
+			assets: Vec::new(),
+			manifest_loaded: false,
+		}
+	}
+}
+
+struct LoadingMaterials {
+	transparent: Handle<ColorMaterial>,
+	background: Handle<ColorMaterial>,
+	progress: Handle<ColorMaterial>,
+}
+
+impl FromWorld for LoadingMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		LoadingMaterials {
+			transparent: materials.add(Color::NONE.into()),
+			background: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+			progress: materials.add(Color::rgb(0.35, 0.75, 0.35).into()),
+		}
+	}
+}
+
+struct ProgressBar;
+
+struct LoadingText;
+
+fn setup_loading(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	materials: Res<LoadingMaterials>,
+) {
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+				flex_direction: FlexDirection::ColumnReverse,
+				justify_content: JustifyContent::Center,
+				align_items: AlignItems::Center,
+				..Default::default()
+			},
+			material: materials.transparent.clone(),
+			..Default::default()
+		})
+		.insert(StateScoped(AppState::Loading))
+		.with_children(|parent| {
+			parent
+				.spawn_bundle(TextBundle {
+					text: Text::with_section(
+						"Loading...",
+						TextStyle {
+							font: asset_server.load("FiraSans-Bold.ttf"),
+							font_size: 32.0,
+							color: Color::rgb(0.9, 0.9, 0.9),
+						},
+						TextAlignment {
+							horizontal: HorizontalAlign::Center,
+							..Default::default()
+						},
+					),
+					..Default::default()
+				})
+				.insert(LoadingText);
+			parent
+				.spawn_bundle(NodeBundle {
+					style: Style {
+						size: Size::new(Val::Percent(40.0), Val::Px(20.0)),
+						margin: Rect {
+							top: Val::Px(20.0),
+							..Default::default()
+						},
+						..Default::default()
+					},
+					material: materials.background.clone(),
+					..Default::default()
+				})
+				.with_children(|parent| {
+					parent
+						.spawn_bundle(NodeBundle {
+							style: Style {
+								size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+								..Default::default()
+							},
+							material: materials.progress.clone(),
+							..Default::default()
+						})
+						.insert(ProgressBar);
+				});
+		});
+}
+
+/// Starts loading everything in the manifest once the manifest itself is loaded.
+fn load_manifest_assets(
+	asset_server: Res<AssetServer>,
+	manifests: Res<Assets<AssetManifest>>,
+	mut loading: ResMut<LoadingAssets>,
+) {
+	if loading.manifest_loaded {
+		return;
+	}
+	let manifest = match manifests.get(&loading.manifest) {
+		Some(manifest) => manifest,
+		None => return,
+	};
+	loading.assets = manifest
+		.paths()
+		.map(|path| (path.clone(), asset_server.load_untyped(path.as_str())))
+		.collect();
+	loading.manifest_loaded = true;
+	info!("Loading {} assets", loading.assets.len());
+}
+
+fn update_loading(
+	args: Res<Args>,
+	asset_server: Res<AssetServer>,
+	loading: Res<LoadingAssets>,
+	mut state: ResMut<State<AppState>>,
+	mut texts: Query<&mut Text, With<LoadingText>>,
+	mut bars: Query<&mut Style, With<ProgressBar>>,
+) {
+	let failed = if asset_server.get_load_state(&loading.manifest) == LoadState::Failed {
+		vec![MANIFEST_PATH]
+	} else {
+		loading
+			.assets
+			.iter()
+			.filter(|(_, handle)| asset_server.get_load_state(handle) == LoadState::Failed)
+			.map(|(path, _)| path.as_str())
+			.collect()
+	};
+	if !failed.is_empty() {
+		let message = format!("Failed to load:\n{}", failed.join("\n"));
+		for mut text in texts.iter_mut() {
+			if text.sections[0].value != message {
+				error!("{}", message);
+				text.sections[0].value = message.clone();
+				text.sections[0].style.color = Color::rgb(0.9, 0.3, 0.3);
+			}
+		}
+		return;
+	}
+
+	if !loading.manifest_loaded {
+		return;
+	}
+	let loaded = loading
+		.assets
+		.iter()
+		.filter(|(_, handle)| asset_server.get_load_state(handle) == LoadState::Loaded)
+		.count();
+	let width = Val::Percent(100.0 * loaded as f32 / loading.assets.len().max(1) as f32);
+	for mut style in bars.iter_mut() {
+		if style.size.width != width {
+			style.size.width = width;
+		}
+	}
+
+	if loaded == loading.assets.len() {
+		let next = AppState::initial(&args);
+		info!("Loaded all assets, going to {:?}", next);
+		if let Err(e) = state.set(next) {
+			warn!("Can't leave the loading screen: {:?}", e);
+		}
+	}
+}
@@ -1,62 +1,158 @@
 use crate::components::*;
-use crate::console::ConsoleComponent;
-use bevy::app::Events;
-use bevy::input::mouse::MouseWheel;
-use bevy::math::{Vec3Swizzles, Vec4Swizzles};
+use bevy::app::{Events, ScheduleRunnerPlugin, ScheduleRunnerSettings};
+use bevy::asset::HandleId;
+use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
-use bevy::render::camera::Camera;
-use bevy::window::WindowResizeConstraints;
+use bevy::utils::{Duration, HashMap};
+use bevy::window::{WindowMode, WindowResizeConstraints};
 
+mod animation;
+mod camera;
+mod checkpoint;
+mod cli;
 mod components;
 mod console;
+mod crosshair;
+mod dungeon;
+mod editor;
+mod effects;
+mod hazards;
+mod high_scores;
+mod hot_reload;
+mod hud;
+mod i18n;
+mod level;
+mod loading;
 mod menu;
-
-static GAME_NAME: &str = "TODO: Wymyśl jakąś nazwę";
+mod net;
+mod physics;
+mod replay;
+mod score;
+mod settings;
+mod stats;
+mod storage;
+mod touch;
+mod ui;
+#[cfg(target_arch = "wasm32")]
+mod web;
+
+/// Window title until the string tables are loaded. Translated as `game-name`.
+static GAME_NAME: &str = "TODO: Come up with a name";
 
 #[bevy_main]
 fn main() {
-	if cfg!(debug_assertions) && std::env::var_os("RUST_LOG").is_none() {
+	let args = cli::Args::from_env();
+	if let Some(cli::Command::Stats(path)) = &args.command {
+		if let Err(e) = stats::print_summary(path) {
+			eprintln!("error: can't summarize {}: {}", path.display(), e);
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	if let Some(level) = args.log_level {
+		std::env::set_var("RUST_LOG", format!("{}={}", env!("CARGO_PKG_NAME"), level));
+	} else if cfg!(debug_assertions) && std::env::var_os("RUST_LOG").is_none() {
 		std::env::set_var("RUST_LOG", concat!(env!("CARGO_PKG_NAME"), "=debug"));
 	}
 
-	App::build()
-		.insert_resource(WindowDescriptor {
-			width: 1280.0,
-			height: 720.0,
-			resize_constraints: WindowResizeConstraints {
-				min_width: 1280.0 / 4.0,
-				min_height: 720.0 / 4.0,
-				max_width: f32::INFINITY,
-				max_height: f32::INFINITY,
-			},
-			scale_factor_override: None,
-			title: GAME_NAME.to_string(),
-			vsync: false,
-			resizable: true,
-			decorations: true,
-			cursor_visible: true,
-			cursor_locked: false,
-			mode: bevy::window::WindowMode::Windowed,
-			#[cfg(target_arch = "wasm32")]
-			canvas: None,
-		})
-		.add_plugins(DefaultPlugins)
+	let (width, height) = args.window.unwrap_or((1280.0, 720.0));
+
+	let mut app = App::build();
+	app.insert_resource(WindowDescriptor {
+		width,
+		height,
+		resize_constraints: WindowResizeConstraints {
+			min_width: cli::MIN_WINDOW_SIZE.0,
+			min_height: cli::MIN_WINDOW_SIZE.1,
+			max_width: f32::INFINITY,
+			max_height: f32::INFINITY,
+		},
+		scale_factor_override: None,
+		title: GAME_NAME.to_string(),
+		vsync: false,
+		resizable: true,
+		decorations: true,
+		cursor_visible: true,
+		cursor_locked: false,
+		mode: if args.fullscreen {
+			WindowMode::BorderlessFullscreen
+		} else {
+			WindowMode::Windowed
+		},
+		#[cfg(target_arch = "wasm32")]
+		canvas: Some(web::CANVAS.to_string()),
+	});
+
+	if args.headless {
+		app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+			1.0 / 60.0,
+		)))
+		.add_plugins_with(DefaultPlugins, |group| {
+			group.disable::<bevy::winit::WinitPlugin>();
+			#[cfg(not(target_arch = "wasm32"))]
+			group.disable::<bevy::wgpu::WgpuPlugin>();
+			group.add(ScheduleRunnerPlugin::default())
+		});
+	} else {
+		app.add_plugins(DefaultPlugins);
+		#[cfg(feature = "web")]
+		app.add_plugin(bevy_webgl2::WebGL2Plugin);
+	}
+
+	app.insert_resource(args)
+		.add_plugin(settings::SettingsPlugin)
+		.add_plugin(ui::UiPlugin)
 		.add_plugin(GamePlugin)
+		.add_plugin(animation::AnimationPlugin)
+		.add_plugin(camera::CameraPlugin)
+		.add_plugin(checkpoint::CheckpointPlugin)
+		.add_plugin(crosshair::CrosshairPlugin)
+		.add_plugin(effects::EffectsPlugin)
+		.add_plugin(hazards::HazardsPlugin)
+		.add_plugin(high_scores::HighScoresPlugin)
+		.add_plugin(hot_reload::HotReloadPlugin)
+		.add_plugin(hud::HudPlugin)
+		.add_plugin(i18n::I18nPlugin)
+		.add_plugin(level::LevelPlugin)
+		.add_plugin(loading::LoadingPlugin)
+		.add_plugin(editor::EditorPlugin)
+		.add_plugin(physics::PhysicsPlugin)
 		.add_plugin(console::ConsolePlugin)
 		.add_plugin(menu::MenuPlugin)
-		.run();
+		.add_plugin(net::NetPlugin)
+		.add_plugin(replay::ReplayPlugin)
+		.add_plugin(score::ScorePlugin)
+		.add_plugin(stats::StatsPlugin)
+		.add_plugin(touch::TouchPlugin);
+	#[cfg(target_arch = "wasm32")]
+	app.add_plugin(web::WebPlugin);
+	app.run();
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
 pub enum AppState {
+	/// Waits for the assets in `assets.manifest`, then goes to [`AppState::initial`].
+	Loading,
 	Game,
 	Menu,
+	/// Shows the score and asks for a name if it made it into the high score table.
 	GameOver,
+	HighScores,
+	Editor,
 }
 
-impl Default for AppState {
-	fn default() -> Self {
-		if std::env::args().any(|arg| arg == "--skip-menu") {
+impl AppState {
+	/// The first state after loading.
+	pub fn initial(args: &cli::Args) -> Self {
+		if args.editor {
+			AppState::Editor
+		} else if args.skip_menu
+			|| args.level.is_some()
+			|| args.replay.is_some()
+			|| args.host.is_some()
+			|| args.connect.is_some()
+		{
 			AppState::Game
 		} else {
 			AppState::Menu
@@ -68,21 +164,31 @@ struct GamePlugin;
 
 impl Plugin for GamePlugin {
 	fn build(&self, app: &mut AppBuilder) {
+		let args = app
+			.world()
+			.get_resource::<cli::Args>()
+			.cloned()
+			.unwrap_or_default();
+		if let Some(seed) = args.seed {
+			info!("Using seed {}", seed);
+		}
+
 		app.add_startup_system(setup_game.system())
-			.insert_resource(Level::hub())
+			.insert_resource(Level::new(args.level.unwrap_or(LevelType::Hub)))
+			.init_resource::<Darkness>()
 			.add_event::<CollisionEvent>()
-			.add_state(AppState::default())
+			.add_state(AppState::Loading)
 			.add_system_set(
 				SystemSet::on_enter(AppState::Game).with_system(spawn_entities.system()),
 			)
-			.add_system_set(SystemSet::on_update(AppState::Game).with_system(player_input.system()))
 			.add_system_set(
-				SystemSet::on_update(AppState::Game).with_system(player_shooting.system()),
+				SystemSet::on_update(AppState::Game)
+					.with_system(player_input.system().after(replay::InputLabel)),
 			)
 			.add_system_set(
-				SystemSet::on_update(AppState::Game).with_system(camera_follow.system()),
+				SystemSet::on_update(AppState::Game)
+					.with_system(player_shooting.system().after(replay::InputLabel)),
 			)
-			.add_system_set(SystemSet::on_update(AppState::Game).with_system(camera_input.system()))
 			.add_system_set(
 				SystemSet::on_update(AppState::Game).with_system(color_change_input.system()),
 			)
@@ -93,13 +199,16 @@ impl Plugin for GamePlugin {
 				SystemSet::on_update(AppState::Game).with_system(detect_portal_collision.system()),
 			)
 			.add_system_set(
-				SystemSet::on_update(AppState::Game).with_system(detect_spikes_collision.system()),
+				SystemSet::on_update(AppState::Game).with_system(detect_hazard_collision.system()),
 			)
 			.add_system_set(
 				SystemSet::on_update(AppState::Game).with_system(detect_missile_collision.system()),
 			)
 			.add_system_set(
 				SystemSet::on_update(AppState::Game).with_system(process_collision_events.system()),
+			)
+			.add_system_set(
+				SystemSet::on_exit(AppState::GameOver).with_system(reset_game.system()),
 			);
 	}
 }
@@ -127,310 +236,306 @@ fn setup_game(
 	asset_server: Res<AssetServer>,
 	mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-	commands
-		.spawn_bundle(OrthographicCameraBundle {
-			transform: Transform {
-				scale: Vec3::new(0.3, 0.3, 1.0),
-				..Default::default()
-			},
-			..OrthographicCameraBundle::new_2d()
-		})
-		.insert(MainCamera);
-
 	let textures: Textures = load_textures!(
 		asset_server,
 		materials,
 		Textures {
-			player_texture => "saitama_fit.png",
 			ground_tile => "ground.png",
 			transparent_ground_tile => "transparent_ground.png",
 			portal_texture => "portal.png",
 			spikes_texture => "spikes.png",
 			missile_texture => "LowPoly_Missile_command_Game_Assets_DevilsGarage_v01/2D/missile_large.png",
+			checkpoint_texture => "red_tree.png",
+			checkpoint_active_texture => "red_tree.png",
+			water_tile => "water.png",
+			bridge_tile => "bridge.png",
+			conveyor_tile => "conveyor.png",
+			enemy_texture => "bird.png",
 		},
 	);
+	// Checkpoints are greyed out until the player activates them.
+	materials
+		.get_mut(&textures.checkpoint_texture)
+		.unwrap()
+		.color = Color::rgb(0.4, 0.4, 0.4);
 
 	commands.insert_resource(textures);
 }
 
-fn spawn_entities(mut commands: Commands, materials: Res<Textures>, level: Res<Level>) {
-	commands
-		.spawn_bundle(SpriteBundle {
-			material: materials.player_texture.clone(),
-			..Default::default()
-		})
-		.insert(Player);
-
-	//TODO use bevy_tilemap
-	for j in
-		((level.size.y / TILE_SIZE / 2.0 * -1.0) as i32)..((level.size.y / TILE_SIZE / 2.0) as i32)
-	{
-		for i in ((level.size.x / TILE_SIZE / 2.0 * -1.0) as i32)
-			..((level.size.x / TILE_SIZE / 2.0) as i32)
-		{
-			let (j, i) = (j as f32, i as f32);
-			commands.spawn_bundle(SpriteBundle {
-				material: materials.ground_tile.clone(),
-				transform: Transform {
-					translation: Vec3::new(i * 32.0, j * 32.0, 0.0),
-					..Default::default()
-				},
-				..Default::default()
-			});
-		}
-	}
-	for j in (((level.size.y + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0 * -1.0) as i32)
-		..(((level.size.y + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0) as i32)
-	{
-		for i in (((level.size.x + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0 * -1.0) as i32)
-			..(((level.size.x + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0) as i32)
-		{
-			if j > ((level.size.y + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0 * -1.0) as i32
-				&& j < (((level.size.y + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0) - 1.0) as i32
-				&& i > ((level.size.x + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0 * -1.0) as i32
-				&& i < (((level.size.x + 2.0 * TILE_SIZE) / TILE_SIZE / 2.0) - 1.0) as i32
-			{
-				continue;
-			}
-
-			let (j, i) = (j as f32, i as f32);
-			commands
-				.spawn_bundle(SpriteBundle {
-					material: materials.transparent_ground_tile.clone(),
-					transform: Transform {
-						translation: Vec3::new(i * 32.0, j * 32.0, 0.0),
-						..Default::default()
-					},
-					..Default::default()
-				})
-				.insert(Collidable);
-		}
-	}
+/// Tint of each player's sprite, so that co-op players can tell each other apart.
+const PLAYER_COLORS: [Color; MAX_PLAYERS] = [Color::WHITE, Color::rgb(0.6, 0.8, 1.0)];
 
-	match level.l_type {
-		LevelType::Hub => setup_level_hub(commands, materials),
-		_ => unimplemented!(),
+fn spawn_entities(
+	mut commands: Commands,
+	settings: Res<settings::Settings>,
+	player_sprite_sheet: Res<animation::PlayerSpriteSheet>,
+	mut respawn_point: ResMut<checkpoint::RespawnPoint>,
+) {
+	*respawn_point = checkpoint::RespawnPoint::default();
+	for (idx, color) in PLAYER_COLORS.iter().enumerate().take(settings.players) {
+		spawn_player(&mut commands, &player_sprite_sheet, idx, *color);
 	}
 }
 
-const MOVEMENT_DELTA: f32 = 100.0;
+fn spawn_player(
+	commands: &mut Commands,
+	player_sprite_sheet: &animation::PlayerSpriteSheet,
+	idx: usize,
+	color: Color,
+) {
+	commands
+		.spawn_bundle(SpriteSheetBundle {
+			texture_atlas: player_sprite_sheet.0.clone(),
+			sprite: TextureAtlasSprite {
+				color,
+				..Default::default()
+			},
+			// Above the tiles and level entities, the first player on top.
+			transform: Transform::from_xyz(0.0, 0.0, (2.0 - 0.1 * idx as f32) * level::ENTITY_Z),
+			..Default::default()
+		})
+		.insert(Player(idx))
+		.insert(ui::StateScoped(AppState::Game))
+		.insert(AimDirection(Vec2::X))
+		.insert(Hitbox(PLAYER_SIZE.into()))
+		.insert(Health::new(PLAYER_MAX_HEALTH))
+		.insert(Weapon::missile_launcher())
+		.insert(MoveDirection(Vec2::ZERO))
+		.insert(hazards::Footing::default())
+		.insert(physics::Velocity::default())
+		.insert(physics::Dash::default())
+		.insert(animation::PlayerAnimation::default());
+}
 
 fn player_input(
-	time: Res<Time>,
-	kb_input: Res<Input<KeyCode>>,
+	mut commands: Commands,
+	inputs: Res<replay::InputFrames>,
 	level: Res<Level>,
-	mut player_translation: Query<&mut Transform, (With<Player>,)>,
+	level_handles: Res<level::LevelHandles>,
+	levels: Res<Assets<level::LevelData>>,
+	configs: Res<Assets<physics::PlayerConfig>>,
+	config_handle: Res<physics::PlayerConfigHandle>,
+	mut player_query: Query<
+		(
+			Entity,
+			&Player,
+			&mut Transform,
+			&mut MoveDirection,
+			&mut physics::Velocity,
+			&mut physics::Dash,
+			&hazards::Footing,
+			Option<&Invulnerable>,
+		),
+		Without<Respawning>,
+	>,
 ) {
-	let delta = MOVEMENT_DELTA * time.delta_seconds();
-	for mut transform in player_translation.iter_mut() {
-		if kb_input.pressed(KeyCode::W) {
-			transform.translation.y =
-				(transform.translation.y + delta).min(level.size.y / 2.0 - TILE_SIZE);
+	let config = config_handle.get(&configs);
+	let level_data = level_handles.get(level.l_type, &levels);
+	for (
+		player,
+		Player(idx),
+		mut transform,
+		mut move_direction,
+		mut velocity,
+		mut dash,
+		footing,
+		invulnerable,
+	) in player_query.iter_mut()
+	{
+		let input = match inputs.get(*idx) {
+			Some(input) => input,
+			None => continue,
+		};
+		let delta = input.delta;
+		move_direction.0 = input.movement();
+
+		dash.cooldown = (dash.cooldown - delta).max(0.0);
+		dash.remaining = (dash.remaining - delta).max(0.0);
+		if input.dash && dash.cooldown == 0.0 && move_direction.0 != Vec2::ZERO {
+			dash.cooldown = config.dash_cooldown;
+			dash.remaining = config.dash_duration;
+			velocity.0 = move_direction.0 * config.dash_speed;
+			if invulnerable.is_none() {
+				commands
+					.entity(player)
+					.insert(Invulnerable(Timer::from_seconds(
+						config.dash_invulnerability,
+						false,
+					)));
+			}
+		}
+		if dash.remaining == 0.0 {
+			let rate = if move_direction.0 == Vec2::ZERO {
+				config.friction
+			} else {
+				config.acceleration
+			};
+			velocity.0 = physics::approach(
+				velocity.0,
+				move_direction.0 * config.max_speed * footing.speed,
+				rate * delta,
+			);
+		}
+
+		if velocity.0 == Vec2::ZERO && footing.push == Vec2::ZERO {
+			continue;
 		}
-		if kb_input.pressed(KeyCode::A) {
-			transform.translation.x =
-				(transform.translation.x - delta).max(level.size.x / -2.0 + TILE_SIZE / 2.0);
+		// Keep the player on the floor tiles inside the wall.
+		let (min, max) = level.bounds();
+		let (min, max) = (
+			min + Vec2::splat(1.5 * TILE_SIZE),
+			max - Vec2::splat(1.5 * TILE_SIZE),
+		);
+		let position = transform.translation.xy() + (velocity.0 + footing.push) * delta;
+		let mut clamped = position.clamp(min, max);
+		// Don't walk off the floor, one axis at a time so that the player slides along the edges.
+		if let Some(data) = level_data {
+			if !data.is_walkable(Vec2::new(clamped.x, transform.translation.y)) {
+				clamped.x = transform.translation.x;
+			}
+			if !data.is_walkable(Vec2::new(clamped.x, clamped.y)) {
+				clamped.y = transform.translation.y;
+			}
 		}
-		if kb_input.pressed(KeyCode::S) {
-			transform.translation.y = (transform.translation.y - delta).max(level.size.y / -2.0);
+		// Stop at the walls instead of pushing into them.
+		if clamped.x != position.x {
+			velocity.0.x = 0.0;
 		}
-		if kb_input.pressed(KeyCode::D) {
-			transform.translation.x =
-				(transform.translation.x + delta).min(level.size.x / 2.0 - TILE_SIZE * 1.5);
+		if clamped.y != position.y {
+			velocity.0.y = 0.0;
 		}
+		transform.translation.x = clamped.x;
+		transform.translation.y = clamped.y;
 	}
 }
 
+#[allow(clippy::too_many_arguments)]
 fn player_shooting(
 	mut commands: Commands,
 	materials: Res<Textures>,
 	material_assets: Res<Assets<ColorMaterial>>,
 	textures: Res<Assets<Texture>>,
-	kb_input: Res<Input<KeyCode>>,
-	windows: Res<Windows>,
-	mouse_input: Res<Input<MouseButton>>,
+	inputs: Res<replay::InputFrames>,
+	net: Res<net::NetMode>,
 	mut console_events: EventWriter<console::ConsoleEvent>,
-	player_query: Query<(&Transform, &Sprite), (With<Player>,)>,
-	camera_query: Query<&Transform, (With<MainCamera>,)>,
+	mut stats_events: EventWriter<stats::StatsEvent>,
+	mut player_query: Query<(
+		Entity,
+		&Player,
+		&Transform,
+		&Hitbox,
+		&mut Weapon,
+		&mut animation::PlayerAnimation,
+	)>,
 ) {
-	if mouse_input.just_pressed(MouseButton::Left) || kb_input.just_pressed(KeyCode::Space) {
-		console_events.send(console::ConsoleEvent::from("fire\n"));
-
-		let window = windows.get_primary().unwrap();
-		let pos = match window.cursor_position() {
-			Some(v) => v,
-			None => {
-				error!("Can't fire without a cursor position");
-				return;
-			}
-		};
-		debug!("Cursor pos: {:?}", pos);
-		let size = Vec2::new(window.width(), window.height());
-		// Offset the cursor from the left bottom origin to the screen center.
-		let p = pos - size / 2.0;
-		let camera_transform = camera_query.single().unwrap();
-		// Translates the cursor position into the game world coordinates.
-		let cursor_world_position =
-			camera_transform.compute_matrix() * Vec4::new(p.x, p.y, 0.0, 1.0);
-		debug!("World coords: {:?}", cursor_world_position);
-		// debug!("Player coords: {:?}", player_query.single().unwrap().translation);
-
-		let missile_texture_size = textures
-			.get(
-				material_assets
-					.get(&materials.missile_texture)
-					.unwrap()
-					.texture
-					.as_ref()
-					.unwrap(),
-			)
-			.unwrap()
-			.size;
-
-		for (
-			Transform {
-				translation: player_translation,
-				..
-			},
-			Sprite {
-				size: player_size, ..
-			},
-		) in player_query.iter()
-		{
-			// Get a vector between the player and the cursor.
-			let mut cursor_relative_to_player =
-				cursor_world_position.xy() - player_translation.xy();
-			// Normalize the cursor position, so that it only represents the direction (has length of 1).
-			cursor_relative_to_player /= cursor_relative_to_player.length();
-			// Calculate the angle between the cursor the player.
-			let mut cursor_angle_relative_to_player = (cursor_relative_to_player.y
-				/ cursor_relative_to_player.x)
-				.atan()
-				.to_degrees();
-			if cursor_relative_to_player.x < 0.0 {
-				cursor_angle_relative_to_player += 180.0;
-			}
-
-			let missile_direction = Vec3::new(
-				cursor_relative_to_player.x,
-				cursor_relative_to_player.y,
-				0.0,
-			);
-
-			// We subtract 90 deg, because the missile sprite is facing up.
-			let missile_angle_relative_to_player =
-				(cursor_angle_relative_to_player - 90.0).to_radians();
-			cursor_angle_relative_to_player = cursor_angle_relative_to_player.to_radians();
-
-			// Radius of the player's shooting circle.
-			let r = player_size.y / 2.0 + missile_texture_size.height as f32 / 2.0;
-			// Calculate missile position at the circle from the cursor angle
-			let missile_translation = Vec3::new(
-				r * cursor_angle_relative_to_player.cos() + player_translation.x,
-				r * cursor_angle_relative_to_player.sin() + player_translation.y,
-				0.0,
-			);
-
-			commands
-				.spawn_bundle(SpriteBundle {
-					material: materials.missile_texture.clone(),
-					transform: Transform {
-						translation: missile_translation,
-						rotation: Quat::from_rotation_z(missile_angle_relative_to_player),
-						scale: Vec3::new(1.0, 1.0, 1.0),
-					},
-					..Default::default()
-				})
-				.insert(Missile {
-					direction: missile_direction,
-					speed: Vec3::new(1.0, 1.0, 1.0),
-					// speed: Vec3::new(0.0, 0.0, 0.0),
-				});
+	for (_, Player(idx), _, _, mut weapon, _) in player_query.iter_mut() {
+		let delta = inputs.get(*idx).map_or(0.0, |input| input.delta);
+		if weapon.cooldown_left > 0.0 {
+			weapon.cooldown_left = (weapon.cooldown_left - delta).max(0.0);
 		}
 	}
-}
 
-fn process_moving_entities(mut missile_query: Query<(&mut Transform, &Missile)>) {
-	//TODO(aqatl): Delta time
-	for (mut missile_transform, missile) in missile_query.iter_mut() {
-		missile_transform.translation += missile.direction * missile.speed;
+	// Clients get the missiles from the host.
+	if !net.is_authoritative() || inputs.0.iter().all(|input| input.fire.is_none()) {
+		return;
 	}
-}
-
-fn camera_follow(
-	mut q: QuerySet<(
-		Query<&Transform, (With<Player>, Changed<Transform>)>,
-		Query<&mut Transform, (With<Camera>, Without<console::ConsoleComponent>)>,
-	)>,
-) {
-	if let Some(player_transform) = q.q0().iter().next() {
-		let (x, y) = (
-			player_transform.translation.x,
-			player_transform.translation.y,
-		);
-		for mut camera_transform in q.q1_mut().iter_mut() {
-			camera_transform.translation.x = x;
-			camera_transform.translation.y = y;
+	// Loaded before the game starts, but don't crash if it was unloaded or failed to reload.
+	let missile_texture_size = match material_assets
+		.get(&materials.missile_texture)
+		.and_then(|material| material.texture.as_ref())
+		.and_then(|texture| textures.get(texture))
+	{
+		Some(texture) => texture.size,
+		None => {
+			warn!("Missile texture isn't loaded");
+			return;
 		}
-	}
-}
-
-fn camera_input(
-	mut scroll_events: EventReader<MouseWheel>,
-	mut q: Query<
-		&mut Transform,
-		(
-			With<bevy::render::camera::Camera>,
-			Without<console::ConsoleComponent>,
-		),
-	>,
-) {
-	for scroll_event in scroll_events.iter() {
-		for mut camera_transform in q.iter_mut() {
-			camera_transform.scale.y += scroll_event.y * 0.05;
-			camera_transform.scale.x += scroll_event.y * 0.05;
+	};
+
+	for (
+		player,
+		Player(idx),
+		Transform {
+			translation: player_translation,
+			..
+		},
+		Hitbox(player_size),
+		mut weapon,
+		mut animation,
+	) in player_query.iter_mut()
+	{
+		let cursor_world_position = match inputs.get(*idx).and_then(|input| input.fire) {
+			Some(target) => target,
+			None => continue,
+		};
+		if weapon.cooldown_left > 0.0 {
+			continue;
+		}
+		weapon.cooldown_left = weapon.cooldown;
+		console_events.send(console::ConsoleEvent::from("fire\n"));
+		stats_events.send(stats::StatsEvent::MissileFired);
+
+		// Get a vector between the player and the cursor.
+		let mut cursor_relative_to_player = cursor_world_position - player_translation.xy();
+		// Normalize the cursor position, so that it only represents the direction (has length of 1).
+		cursor_relative_to_player /= cursor_relative_to_player.length();
+		animation.shoot(cursor_relative_to_player);
+		// Calculate the angle between the cursor the player.
+		let mut cursor_angle_relative_to_player = (cursor_relative_to_player.y
+			/ cursor_relative_to_player.x)
+			.atan()
+			.to_degrees();
+		if cursor_relative_to_player.x < 0.0 {
+			cursor_angle_relative_to_player += 180.0;
 		}
-	}
-}
 
-fn setup_level_hub(mut commands: Commands, materials: Res<Textures>) {
-	info!("Spawning hub level entities");
-	commands
-		.spawn_bundle(SpriteBundle {
-			material: materials.portal_texture.clone(),
-			transform: Transform {
-				translation: Vec3::new(3.0 * TILE_SIZE, 4.0 * TILE_SIZE, 0.0),
-				..Default::default()
-			},
-			..Default::default()
-		})
-		.insert(PortalDestination(LevelType::Level1))
-		.insert(Collidable);
-	commands
-		.spawn_bundle(SpriteBundle {
-			material: materials.portal_texture.clone(),
-			transform: Transform {
-				translation: Vec3::new(-7.0 * TILE_SIZE, -5.0 * TILE_SIZE, 0.0),
-				..Default::default()
-			},
-			..Default::default()
-		})
-		.insert(PortalDestination(LevelType::Secret1))
-		.insert(Collidable);
+		let missile_direction = Vec3::new(
+			cursor_relative_to_player.x,
+			cursor_relative_to_player.y,
+			0.0,
+		);
+
+		// We subtract 90 deg, because the missile sprite is facing up.
+		let missile_angle_relative_to_player =
+			(cursor_angle_relative_to_player - 90.0).to_radians();
+		cursor_angle_relative_to_player = cursor_angle_relative_to_player.to_radians();
+
+		// Radius of the player's shooting circle.
+		let r = player_size.y / 2.0 + missile_texture_size.height as f32 / 2.0;
+		// Calculate missile position at the circle from the cursor angle
+		let missile_translation = Vec3::new(
+			r * cursor_angle_relative_to_player.cos() + player_translation.x,
+			r * cursor_angle_relative_to_player.sin() + player_translation.y,
+			player_translation.z,
+		);
 
-	let spikes_locations = [(-1.0, 1.0), (-1.0, 2.0), (-3.0, -1.0)];
-	for (spike_x_idx, spike_y_idx) in spikes_locations.iter() {
 		commands
 			.spawn_bundle(SpriteBundle {
-				material: materials.spikes_texture.clone(),
+				material: materials.missile_texture.clone(),
 				transform: Transform {
-					translation: Vec3::new(spike_x_idx * TILE_SIZE, spike_y_idx * TILE_SIZE, 0.0),
-					..Default::default()
+					translation: missile_translation,
+					rotation: Quat::from_rotation_z(missile_angle_relative_to_player),
+					scale: Vec3::new(1.0, 1.0, 1.0),
 				},
 				..Default::default()
 			})
-			.insert(Spikes)
-			.insert(Collidable);
+			.insert(Missile {
+				owner: player,
+				direction: missile_direction,
+				speed: Vec3::new(1.0, 1.0, 1.0),
+				// speed: Vec3::new(0.0, 0.0, 0.0),
+			})
+			.insert(ui::StateScoped(AppState::Game));
+	}
+}
+
+fn process_moving_entities(
+	time_scale: Res<effects::TimeScale>,
+	mut missile_query: Query<(&mut Transform, &Missile)>,
+) {
+	//TODO(aqatl): Delta time
+	for (mut missile_transform, missile) in missile_query.iter_mut() {
+		missile_transform.translation += missile.direction * missile.speed * time_scale.0;
 	}
 }
 
@@ -454,10 +559,14 @@ fn color_change_input(kb_input: Res<Input<KeyCode>>, mut materials: ResMut<Asset
 }
 
 fn detect_portal_collision(
+	net: Res<net::NetMode>,
 	portals: Query<(&Transform, &PortalDestination), With<PortalDestination>>,
 	players: Query<&Transform, (With<Player>, Changed<Transform>)>,
 	mut collision_events: ResMut<Events<CollisionEvent>>,
 ) {
+	if !net.is_authoritative() {
+		return;
+	}
 	for player in players.iter() {
 		for (portal, portal_destination) in portals.iter() {
 			// To increase tolerance, increase this                ---\/
@@ -471,33 +580,96 @@ fn detect_portal_collision(
 	}
 }
 
-fn detect_spikes_collision(
-	spikes: Query<(&Transform, &Sprite), With<Spikes>>,
-	players: Query<(&Transform, &Sprite), (With<Player>, Changed<Transform>)>,
+fn detect_hazard_collision(
+	net: Res<net::NetMode>,
+	hazards: Query<(&Transform, &Sprite, &hazards::Hazard, Option<&Spikes>)>,
+	players: Query<
+		(Entity, &Transform, &Hitbox, &hazards::Footing),
+		(With<Player>, Without<Invulnerable>, Without<Respawning>),
+	>,
 	mut collision_events: ResMut<Events<CollisionEvent>>,
+	mut stats_events: EventWriter<stats::StatsEvent>,
 ) {
-	for (player, player_sprite) in players.iter() {
-		for (spike, spike_sprite) in spikes.iter() {
-			if (player.translation.x - spike.translation.x).abs() * 2.1
-				< (player_sprite.size.x + spike_sprite.size.x)
-				&& (player.translation.y - spike.translation.y).abs() * 2.1
-					< (player_sprite.size.y + spike_sprite.size.y)
+	if !net.is_authoritative() {
+		return;
+	}
+	for (player_entity, player, Hitbox(player_size), footing) in players.iter() {
+		if let Some(damage) = footing.drowning {
+			info!("player drowned");
+			collision_events.send(CollisionEvent::Hazard {
+				player: player_entity,
+				damage,
+			});
+		}
+		for (hazard, hazard_sprite, hazards::Hazard { damage, active }, spikes) in hazards.iter() {
+			if !active {
+				continue;
+			}
+			if (player.translation.x - hazard.translation.x).abs() * 2.1
+				< (player_size.x + hazard_sprite.size.x)
+				&& (player.translation.y - hazard.translation.y).abs() * 2.1
+					< (player_size.y + hazard_sprite.size.y)
 			{
-				info!("player touched spikes");
-				collision_events.send(CollisionEvent::Spikes);
+				info!("player touched a hazard");
+				if spikes.is_some() {
+					stats_events.send(stats::StatsEvent::SpikeCollision);
+				}
+				collision_events.send(CollisionEvent::Hazard {
+					player: player_entity,
+					damage: *damage,
+				});
 			}
 		}
 	}
 }
 
+#[allow(clippy::too_many_arguments)]
 fn detect_missile_collision(
 	mut commands: Commands,
-	missile_q: Query<(&Transform, &Sprite, Entity), (With<Missile>,)>,
-	collidiable_q: Query<(&Transform, &Sprite, Entity), (With<Collidable>, Without<Missile>)>,
-	// mut collision_events: ResMut<Events<CollisionEvent>>,
+	settings: Res<settings::Settings>,
+	net: Res<net::NetMode>,
+	missile_q: Query<(&Transform, &Sprite, Entity, &Missile)>,
+	collidiable_q: Query<
+		(&Transform, &Sprite, Entity, Option<&Enemy>),
+		(With<Collidable>, Without<Missile>),
+	>,
+	players: Query<
+		(Entity, &Transform, &Hitbox),
+		(With<Player>, Without<Invulnerable>, Without<Respawning>),
+	>,
+	mut collision_events: EventWriter<CollisionEvent>,
+	mut explosion_events: EventWriter<effects::ExplosionEvent>,
+	mut score_events: EventWriter<score::ScoreEvent>,
+	mut stats_events: EventWriter<stats::StatsEvent>,
 ) {
-	for (missile_transform, missile_sprite, missile_entity) in missile_q.iter() {
-		for (collidable_transform, collidable_sprite, collidable_entity) in collidiable_q.iter() {
+	if !net.is_authoritative() {
+		return;
+	}
+	for (missile_transform, missile_sprite, missile_entity, missile) in missile_q.iter() {
+		// Missiles fly through the other players unless friendly fire is on.
+		let hit_player = players
+			.iter()
+			.filter(|(player, ..)| settings.friendly_fire && *player != missile.owner)
+			.find(|(_, player_transform, Hitbox(player_size))| {
+				(missile_transform.translation.x - player_transform.translation.x).abs() * 2.0
+					< (missile_sprite.size.x / 2.0 + player_size.x)
+					&& (missile_transform.translation.y - player_transform.translation.y).abs()
+						* 2.0 < (missile_sprite.size.y / 2.0 + player_size.y)
+			});
+		if let Some((player, ..)) = hit_player {
+			info!("missile hit player {:?}", player);
+			commands.entity(missile_entity).despawn_recursive();
+			stats_events.send(stats::StatsEvent::Hit);
+			collision_events.send(CollisionEvent::FriendlyFire { player });
+			explosion_events.send(effects::ExplosionEvent {
+				position: missile_transform.translation,
+			});
+			continue;
+		}
+
+		for (collidable_transform, collidable_sprite, collidable_entity, enemy) in
+			collidiable_q.iter()
+		{
 			if (missile_transform.translation.x - collidable_transform.translation.x).abs() * 2.1
 				< (missile_sprite.size.x / 2.0 + collidable_sprite.size.x / 1.0)
 				&& (missile_transform.translation.y - collidable_transform.translation.y).abs()
@@ -507,6 +679,14 @@ fn detect_missile_collision(
 				// only?
 				info!("missile collided with entity {:?}", collidable_entity);
 				commands.entity(missile_entity).despawn_recursive();
+				stats_events.send(stats::StatsEvent::Hit);
+				if enemy.is_some() {
+					commands.entity(collidable_entity).despawn_recursive();
+					score_events.send(score::ScoreEvent::EnemyKilled);
+				}
+				explosion_events.send(effects::ExplosionEvent {
+					position: missile_transform.translation,
+				});
 			}
 		}
 	}
@@ -515,68 +695,144 @@ fn detect_missile_collision(
 #[derive(Debug)]
 enum CollisionEvent {
 	Portal(PortalDestination),
-	Spikes,
+	/// The player touched a hazard dealing this much damage.
+	Hazard {
+		player: Entity,
+		damage: u32,
+	},
+	/// The player was hit by another player's missile.
+	FriendlyFire {
+		player: Entity,
+	},
 }
 
 const BRIGHTNESS_DELTA: f32 = 0.04;
+/// One hit point per brightness step, so the health runs out when the world goes dark. In co-op
+/// the steps are split between the players, so it goes dark when everyone's health runs out.
+const PLAYER_MAX_HEALTH: u32 = (1.0 / BRIGHTNESS_DELTA) as u32;
+const FRIENDLY_FIRE_DAMAGE: u32 = 2;
 
 fn process_collision_events(
+	mut commands: Commands,
+	settings: Res<settings::Settings>,
 	mut collision_events: EventReader<CollisionEvent>,
 	mut console_events: EventWriter<console::ConsoleEvent>,
+	mut travel_events: EventWriter<level::TravelEvent>,
+	mut score_events: EventWriter<score::ScoreEvent>,
+	mut stats_events: EventWriter<stats::StatsEvent>,
 	mut materials: ResMut<Assets<ColorMaterial>>,
-	mut player_transform_query: Query<&mut Transform, Or<(With<Player>, With<Camera>)>>,
+	mut darkness: ResMut<Darkness>,
+	respawn_point: Res<checkpoint::RespawnPoint>,
+	mut player_query: Query<
+		(Entity, &mut Transform, &mut Health),
+		(With<Player>, Without<MainCamera>),
+	>,
+	mut camera_query: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
 	mut state: ResMut<State<AppState>>,
-	console_entities: Query<&Handle<ColorMaterial>, With<ConsoleComponent>>,
+	ui_materials: Query<&Handle<ColorMaterial>, With<Node>>,
 ) {
 	for collision_event in collision_events.iter() {
 		println!("collision event start");
-		match collision_event {
-			CollisionEvent::Spikes => {
-				change_brightness(&mut materials, &console_entities, &mut state);
-				reset_player_position(&mut player_transform_query);
-			}
+		let log_msg = format!("Collision detected with: {:?}\n", collision_event);
+		console_events.send(console::ConsoleEvent::Log(log_msg));
+		let (player, damage) = match collision_event {
+			CollisionEvent::Hazard { player, damage } => (*player, *damage),
+			CollisionEvent::FriendlyFire { player } => (*player, FRIENDLY_FIRE_DAMAGE),
 			CollisionEvent::Portal(destination) => {
 				info!("player entered portal to {:?}", destination);
+				travel_events.send(level::TravelEvent(destination.0));
+				continue;
 			}
+		};
+
+		score_events.send(score::ScoreEvent::Damaged(damage));
+		let step = BRIGHTNESS_DELTA / settings.players as f32;
+		let mut game_over = false;
+		for _ in 0..damage {
+			game_over |= change_brightness(&mut materials, &mut darkness, step, &ui_materials);
+		}
+		let others_alive = player_query
+			.iter_mut()
+			.any(|(other, _, health)| other != player && health.current > 0);
+		if let Ok((_, mut player_transform, mut health)) = player_query.get_mut(player) {
+			if health.current > 0 && health.current <= damage {
+				stats_events.send(stats::StatsEvent::Death);
+			}
+			health.current = health.current.saturating_sub(damage);
+			if health.current == 0 && others_alive {
+				// Out for the rest of the game, the others play on.
+				info!("player {:?} is out", player);
+				commands.entity(player).despawn_recursive();
+			} else {
+				game_over |= health.current == 0;
+				checkpoint::respawn_player(
+					&mut commands,
+					&respawn_point,
+					player,
+					&mut player_transform,
+				);
+			}
+		}
+		// In co-op the camera frames the other players instead.
+		if !others_alive {
+			for mut camera_transform in camera_query.iter_mut() {
+				camera_transform.translation.x = respawn_point.0.x;
+				camera_transform.translation.y = respawn_point.0.y;
+			}
+		}
+		// Another event in this frame may have already ended the game.
+		if game_over && state.set(AppState::GameOver).is_ok() {
+			warn!("Game over");
 		}
-		let log_msg = format!("Collision detected with: {:?}\n", collision_event);
-		console_events.send(console::ConsoleEvent::Log(log_msg))
 	}
 }
 
+/// Brightness taken by [`change_brightness`] from every material, given back when a new game
+/// starts.
+#[derive(Default)]
+struct Darkness(HashMap<HandleId, f32>);
+
+/// Darkens everything except the UI by `step`. Returns whether the world went completely black.
 fn change_brightness(
 	materials: &mut Assets<ColorMaterial>,
-	console_entities: &Query<&Handle<ColorMaterial>, With<ConsoleComponent>>,
-	state: &mut State<AppState>,
-) {
-	let delta = Vec4::new(BRIGHTNESS_DELTA, BRIGHTNESS_DELTA, BRIGHTNESS_DELTA, 0.0);
+	darkness: &mut Darkness,
+	step: f32,
+	ui_materials: &Query<&Handle<ColorMaterial>, With<Node>>,
+) -> bool {
+	let delta = Vec4::new(step, step, step, 0.0);
 
 	let ids = materials
 		.iter()
 		.map(|(id, _)| id)
-		.filter(|id| !console_entities.iter().any(|con_id| con_id.id == *id))
+		.filter(|id| !ui_materials.iter().any(|ui_id| ui_id.id == *id))
 		.collect::<Vec<_>>();
 
 	let mut all_black = true;
 	for id in ids {
 		let material = materials.get_mut(id).unwrap();
 		material.color = material.color + delta * -1.0;
+		*darkness.0.entry(id).or_default() += step;
 		if material.color.r() > 0.0 && material.color.g() > 0.0 && material.color.b() > 0.0 {
 			all_black = false;
 		}
 	}
 
-	if all_black {
-		warn!("Game over");
-		state.set(AppState::GameOver).unwrap();
-	}
+	all_black
 }
 
-fn reset_player_position(
-	player_transform_query: &mut Query<&mut Transform, Or<(With<Player>, With<Camera>)>>,
+/// Clears what's left of the finished game, so that the next one starts from scratch.
+fn reset_game(
+	args: Res<cli::Args>,
+	mut level: ResMut<Level>,
+	mut materials: ResMut<Assets<ColorMaterial>>,
+	mut darkness: ResMut<Darkness>,
 ) {
-	for mut transform in player_transform_query.iter_mut() {
-		transform.translation.x = 0.0;
-		transform.translation.y = 0.0;
+	// The level itself is respawned when the game starts.
+	level.l_type = args.level.unwrap_or(LevelType::Hub);
+
+	for (id, delta) in darkness.0.drain() {
+		if let Some(material) = materials.get_mut(id) {
+			material.color = material.color + Vec4::new(delta, delta, delta, 0.0);
+		}
 	}
 }
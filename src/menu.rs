@@ -1,157 +1,265 @@
+use crate::i18n::{Locale, Localized};
+use crate::replay::ActiveGamepad;
+use crate::ui::StateScoped;
 use crate::AppState;
 use bevy::app::AppExit;
 use bevy::app::Events;
 use bevy::prelude::*;
-// use bevy::input::keyboard::KeyCode::Apps;
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
 	fn build(&self, app: &mut AppBuilder) {
 		app.init_resource::<ButtonMaterials>()
-			.add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu.system()))
-			.add_system_set(SystemSet::on_update(AppState::Menu).with_system(update_menu.system()))
-			.add_system_set(SystemSet::on_exit(AppState::Menu).with_system(destroy_menu.system()));
+			.init_resource::<MenuFocus>()
+			// Runs in every state, so that menus of other states only need to be spawned.
+			.add_system(update_menu.system())
+			.add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu.system()));
 	}
 }
 
-#[derive(Debug)]
+/// What a menu button does when activated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ButtonBehavior {
+	/// Switches to the given state.
+	Goto(AppState),
 	Exit,
-	Play,
 }
 
-pub fn setup_menu(
-	mut commands: Commands,
-	asset_server: Res<AssetServer>,
-	button_materials: Res<ButtonMaterials>,
-) {
-	commands.spawn_bundle(UiCameraBundle::default());
+/// One button of a menu.
+#[derive(Debug)]
+pub struct MenuItem {
+	/// Translation key of the label.
+	pub label: &'static str,
+	/// Translation key of the label while the button is hovered or focused.
+	pub hover_label: &'static str,
+	/// Translation key of the label while the button is pressed.
+	pub pressed_label: &'static str,
+	pub action: ButtonBehavior,
+}
+
+pub static MAIN_MENU: [MenuItem; 3] = [
+	MenuItem {
+		label: "menu-play",
+		hover_label: "menu-play-hover",
+		pressed_label: "menu-play-pressed",
+		action: ButtonBehavior::Goto(AppState::Game),
+	},
+	MenuItem {
+		label: "menu-high-scores",
+		hover_label: "menu-high-scores-hover",
+		pressed_label: "menu-high-scores-pressed",
+		action: ButtonBehavior::Goto(AppState::HighScores),
+	},
+	MenuItem {
+		label: "menu-exit",
+		hover_label: "menu-exit-hover",
+		pressed_label: "menu-exit-pressed",
+		action: ButtonBehavior::Exit,
+	},
+];
+
+const PREVIOUS_KEYS: [KeyCode; 4] = [KeyCode::Up, KeyCode::W, KeyCode::Left, KeyCode::A];
+const NEXT_KEYS: [KeyCode; 4] = [KeyCode::Down, KeyCode::S, KeyCode::Right, KeyCode::D];
+const ACTIVATE_KEYS: [KeyCode; 3] = [KeyCode::Return, KeyCode::NumpadEnter, KeyCode::Space];
+
+/// Index of the focused item of the open menu. Follows the mouse too, so that only one button is
+/// highlighted at a time.
+#[derive(Default)]
+pub struct MenuFocus(Option<usize>);
+
+/// Root of a menu spawned by [`spawn_menu`]. Despawning it removes the whole menu, e.g. with a
+/// [`StateScoped`] marker.
+pub struct MenuRoot {
+	items: &'static [MenuItem],
+}
+
+/// Button of the item at this index of [`MenuRoot::items`].
+struct MenuButton(usize);
+
+/// Spawns a column of buttons in the middle of the screen, one for every item, top to bottom.
+pub fn spawn_menu(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	locale: &Locale,
+	materials: &ButtonMaterials,
+	focus: &mut MenuFocus,
+	gamepad: &ActiveGamepad,
+	items: &'static [MenuItem],
+) -> Entity {
+	// Gamepads have no pointer, so start with the first button focused.
+	focus.0 = gamepad.0.map(|_| 0);
+	let font = asset_server.load("FiraSans-Bold.ttf");
+
 	commands
-		.spawn_bundle(ButtonBundle {
+		.spawn_bundle(NodeBundle {
 			style: Style {
-				size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-				// center button
-				margin: Rect::all(Val::Auto),
-				// horizontally center child text
+				size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+				flex_direction: FlexDirection::ColumnReverse,
 				justify_content: JustifyContent::Center,
-				// vertically center child text
 				align_items: AlignItems::Center,
 				..Default::default()
 			},
-			material: button_materials.normal.clone(),
+			material: materials.transparent.clone(),
 			..Default::default()
 		})
+		.insert(MenuRoot { items })
 		.with_children(|parent| {
-			parent
-				.spawn_bundle(TextBundle {
-					text: Text {
-						sections: vec![TextSection {
-							value: "Ready?".to_string(),
-							style: TextStyle {
-								font_size: 40.0,
-								color: Color::rgb(0.9, 0.9, 0.9),
-								font: asset_server.load("FiraSans-Bold.ttf"),
-							},
-						}],
-						alignment: Default::default(),
-					},
-					..Default::default()
-				})
-				.insert(ButtonBehavior::Play);
-		});
-	commands
-		.spawn_bundle(ButtonBundle {
-			style: Style {
-				size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-				// center button
-				margin: Rect::all(Val::Auto),
-				// horizontally center child text
-				justify_content: JustifyContent::Center,
-				// vertically center child text
-				align_items: AlignItems::Center,
-				..Default::default()
-			},
-			material: button_materials.normal.clone(),
-			..Default::default()
+			for (idx, item) in items.iter().enumerate() {
+				parent
+					.spawn_bundle(ButtonBundle {
+						style: Style {
+							min_size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+							margin: Rect::all(Val::Px(10.0)),
+							padding: Rect::all(Val::Px(10.0)),
+							// center the label
+							justify_content: JustifyContent::Center,
+							align_items: AlignItems::Center,
+							..Default::default()
+						},
+						material: materials.normal.clone(),
+						..Default::default()
+					})
+					.insert(MenuButton(idx))
+					.with_children(|parent| {
+						parent
+							.spawn_bundle(TextBundle {
+								text: Text::with_section(
+									locale.get(item.label),
+									TextStyle {
+										font_size: 40.0,
+										color: Color::rgb(0.9, 0.9, 0.9),
+										font: font.clone(),
+									},
+									Default::default(),
+								),
+								..Default::default()
+							})
+							.insert(Localized(item.label));
+					});
+			}
 		})
-		.with_children(|parent| {
-			parent
-				.spawn_bundle(TextBundle {
-					text: Text {
-						sections: vec![TextSection {
-							value: "Bored".to_string(),
-							style: TextStyle {
-								font_size: 40.0,
-								color: Color::rgb(0.9, 0.9, 0.9),
-								font: asset_server.load("FiraSans-Bold.ttf"),
-							},
-						}],
-						alignment: Default::default(),
-					},
-					..Default::default()
-				})
-				.insert(ButtonBehavior::Exit);
-		});
+		.id()
 }
 
-pub fn destroy_menu(
+pub fn setup_menu(
 	mut commands: Commands,
-	mut interaction_query: Query<(Entity, &Button, &Children)>,
+	asset_server: Res<AssetServer>,
+	locale: Res<Locale>,
+	button_materials: Res<ButtonMaterials>,
+	gamepad: Res<ActiveGamepad>,
+	mut focus: ResMut<MenuFocus>,
 ) {
-	for (but, _, _) in interaction_query.iter_mut() {
-		commands.entity(but).despawn_recursive();
-	}
+	let menu = spawn_menu(
+		&mut commands,
+		&asset_server,
+		&locale,
+		&button_materials,
+		&mut focus,
+		&gamepad,
+		&MAIN_MENU,
+	);
+	commands.entity(menu).insert(StateScoped(AppState::Menu));
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn update_menu(
+	kb_input: Res<Input<KeyCode>>,
+	gamepad_input: Res<Input<GamepadButton>>,
+	gamepad: Res<ActiveGamepad>,
 	button_materials: Res<ButtonMaterials>,
-	mut interaction_query: Query<
-		(&Interaction, &mut Handle<ColorMaterial>, &Children),
-		(Changed<Interaction>, With<Button>),
-	>,
-	mut text_query: Query<(&mut Text, &ButtonBehavior)>,
+	mut focus: ResMut<MenuFocus>,
+	menus: Query<&MenuRoot>,
+	mut buttons: Query<(
+		&Interaction,
+		ChangeTrackers<Interaction>,
+		&MenuButton,
+		&mut Handle<ColorMaterial>,
+		&Children,
+	)>,
+	mut labels: Query<&mut Localized>,
 	mut state: ResMut<State<AppState>>,
 	mut exit_signal: ResMut<Events<AppExit>>,
 ) {
-	for (interaction, mut material, children) in interaction_query.iter_mut() {
-		let (mut text, behavior) = text_query.get_mut(children[0]).unwrap();
-		match *behavior {
-			ButtonBehavior::Play => match *interaction {
-				Interaction::Clicked => {
-					text.sections.iter_mut().next().unwrap().value = "Loading...".to_string();
-					*material = button_materials.pressed.clone();
-					state.set(AppState::Game).unwrap();
-				}
-				Interaction::Hovered => {
-					text.sections.iter_mut().next().unwrap().value = "Start!".to_string();
-					*material = button_materials.hovered.clone();
-				}
-				Interaction::None => {
-					text.sections.iter_mut().next().unwrap().value = "Ready?".to_string();
-					*material = button_materials.normal.clone();
-				}
-			},
-			ButtonBehavior::Exit => match *interaction {
-				Interaction::Clicked => {
-					text.sections.iter_mut().next().unwrap().value = "Exiting...".to_string();
-					*material = button_materials.pressed.clone();
-					exit_signal.send(AppExit);
-				}
-				Interaction::Hovered => {
-					text.sections.iter_mut().next().unwrap().value = "Exit!".to_string();
-					*material = button_materials.hovered.clone();
-				}
-				Interaction::None => {
-					text.sections.iter_mut().next().unwrap().value = "Bored?".to_string();
-					*material = button_materials.normal.clone();
+	let items = match menus.iter().next() {
+		Some(menu) => menu.items,
+		None => return,
+	};
+
+	let just_pressed = |keys: &[KeyCode], button_type: GamepadButtonType| {
+		keys.iter().any(|key| kb_input.just_pressed(*key))
+			|| gamepad.0.map_or(false, |gamepad| {
+				gamepad_input.just_pressed(GamepadButton(gamepad, button_type))
+			})
+	};
+	let step = if just_pressed(&PREVIOUS_KEYS, GamepadButtonType::DPadUp) {
+		-1
+	} else if just_pressed(&NEXT_KEYS, GamepadButtonType::DPadDown) {
+		1
+	} else {
+		0
+	};
+	if step != 0 {
+		focus.0 = Some(match focus.0 {
+			Some(idx) => (idx as isize + step).rem_euclid(items.len() as isize) as usize,
+			None => 0,
+		});
+	}
+	let activated = focus
+		.0
+		.filter(|_| just_pressed(&ACTIVATE_KEYS, GamepadButtonType::South));
+
+	for (interaction, trackers, MenuButton(idx), ..) in buttons.iter_mut() {
+		if !trackers.is_changed() {
+			continue;
+		}
+		match interaction {
+			Interaction::Hovered => focus.0 = Some(*idx),
+			Interaction::None if focus.0 == Some(*idx) => focus.0 = None,
+			_ => {}
+		}
+	}
+
+	for (interaction, trackers, MenuButton(idx), mut material, children) in buttons.iter_mut() {
+		let item = match items.get(*idx) {
+			Some(item) => item,
+			None => continue,
+		};
+		let clicked = trackers.is_changed() && *interaction == Interaction::Clicked;
+		let activate = clicked || activated == Some(*idx);
+
+		let (label, next_material) = if activate || *interaction == Interaction::Clicked {
+			(item.pressed_label, &button_materials.pressed)
+		} else if focus.0 == Some(*idx) {
+			(item.hover_label, &button_materials.hovered)
+		} else {
+			(item.label, &button_materials.normal)
+		};
+		if let Ok(mut localized) = labels.get_mut(children[0]) {
+			if localized.0 != label {
+				localized.0 = label;
+			}
+		}
+		if *material != *next_material {
+			*material = next_material.clone();
+		}
+
+		if !activate {
+			continue;
+		}
+		match item.action {
+			ButtonBehavior::Goto(next) => {
+				if state.set(next).is_ok() {
+					info!("Menu switching to {:?}", next);
 				}
-			},
+			}
+			ButtonBehavior::Exit => exit_signal.send(AppExit),
 		}
 	}
 }
 
 pub struct ButtonMaterials {
+	transparent: Handle<ColorMaterial>,
 	normal: Handle<ColorMaterial>,
 	hovered: Handle<ColorMaterial>,
 	pressed: Handle<ColorMaterial>,
@@ -161,6 +269,7 @@ impl FromWorld for ButtonMaterials {
 	fn from_world(world: &mut World) -> Self {
 		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
 		ButtonMaterials {
+			transparent: materials.add(Color::NONE.into()),
 			normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
 			hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
 			pressed: materials.add(Color::rgb(0.35, 0.75, 0.35).into()),
@@ -0,0 +1,873 @@
+use crate::cli::Args;
+use crate::components::*;
+use crate::dungeon::Dungeon;
+use crate::effects::ExplosionEvent;
+use crate::level::{LevelData, LevelHandles, LevelSpawner};
+use crate::replay::{InputFrame, InputFrames, InputLabel, SampleLabel};
+use crate::settings::Settings;
+use crate::ui::StateScoped;
+use crate::AppState;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+
+/// Seconds without a packet from the other side after which it's considered gone.
+const TIMEOUT: f64 = 5.0;
+/// Seconds between the connection attempts of a client.
+const HELLO_INTERVAL: f64 = 1.0;
+/// Unacknowledged inputs a client sends again with every packet, so that a lost packet doesn't
+/// lose a dash or a shot.
+const MAX_RESENT_INPUTS: usize = 8;
+/// Inputs a client remembers for reconciling its prediction while the host doesn't answer.
+const MAX_HISTORY: usize = 256;
+/// Prediction errors up to this many pixels are left alone.
+const RECONCILE_THRESHOLD: f32 = 1.0;
+/// Most seconds of a client's inputs the host applies in one frame. The rest waits for the next
+/// frames, so that a burst of inputs after a lag spike doesn't teleport the player.
+const MAX_STEP: f32 = 0.1;
+/// Largest UDP payload.
+const MAX_PACKET_SIZE: usize = 65_507;
+
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		let args = app
+			.world()
+			.get_resource::<Args>()
+			.cloned()
+			.unwrap_or_default();
+		app.insert_resource(NetMode::from_args(&args))
+			.add_system(receive_packets.system().label(ReceiveLabel))
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+					.with_system(
+						apply_snapshot
+							.system()
+							.after(ReceiveLabel)
+							.before(SampleLabel),
+					)
+					.with_system(
+						exchange_input
+							.system()
+							.label(InputLabel)
+							.after(ReceiveLabel)
+							.after(SampleLabel),
+					),
+			)
+			// After the commands of the game systems are applied, so that new missiles are sent.
+			.add_system_to_stage(CoreStage::PostUpdate, send_snapshots.system());
+	}
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct ReceiveLabel;
+
+/// Whether the game is played alone, hosted for other instances or joined over the network.
+pub enum NetMode {
+	Offline,
+	Host(Host),
+	Client(Client),
+}
+
+impl NetMode {
+	fn from_args(args: &Args) -> Self {
+		if let Some(port) = args.host {
+			match Host::bind(port) {
+				Ok(host) => {
+					info!("Hosting on UDP port {}", port);
+					return NetMode::Host(host);
+				}
+				Err(e) => error!("Failed to host on UDP port {}: {}", port, e),
+			}
+		} else if let Some(address) = args.connect {
+			match Client::bind(address) {
+				Ok(client) => {
+					info!("Connecting to {}", address);
+					return NetMode::Client(client);
+				}
+				Err(e) => error!("Failed to connect to {}: {}", address, e),
+			}
+		}
+		NetMode::Offline
+	}
+
+	/// Whether this instance decides the collisions and their outcome. In a networked game only
+	/// the host does, and the clients show what it sends them.
+	pub fn is_authoritative(&self) -> bool {
+		!matches!(self, NetMode::Client(_))
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ClientMessage {
+	/// Asks for a player.
+	Hello,
+	/// Inputs numbered from `first`, in the format of the input recordings.
+	Inputs { first: u32, frames: Vec<String> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum HostMessage {
+	Welcome {
+		player: usize,
+		/// Dungeon seed, so that the client generates the same floors.
+		seed: u64,
+	},
+	/// Every player is taken.
+	Full,
+	Snapshot(Snapshot),
+}
+
+/// State of the game on the host, sent to the clients every frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Snapshot {
+	tick: u64,
+	/// Number of the last input of the receiving client the host applied.
+	ack: Option<u32>,
+	level: LevelType,
+	depth: u64,
+	score: u32,
+	game_over: bool,
+	players: Vec<PlayerState>,
+	/// Missiles inside the level.
+	missiles: Vec<MissileState>,
+	/// Positions of the enemies still alive.
+	enemies: Vec<[f32; 2]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlayerState {
+	player: usize,
+	position: [f32; 2],
+	health: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MissileState {
+	/// The missile entity on the host.
+	id: u64,
+	position: [f32; 3],
+	direction: [f32; 2],
+}
+
+/// Runs the game for itself and the clients, which play the other players.
+pub struct Host {
+	socket: UdpSocket,
+	peers: Vec<Peer>,
+	tick: u64,
+}
+
+/// A client playing one of the players.
+struct Peer {
+	address: SocketAddr,
+	player: usize,
+	/// Seconds since startup.
+	last_heard: f64,
+	/// Number of the last received input.
+	received: Option<u32>,
+	/// Number of the last input applied to the player.
+	applied: Option<u32>,
+	/// Numbered inputs waiting to be applied, oldest first.
+	queue: VecDeque<(u32, InputFrame)>,
+}
+
+impl Host {
+	fn bind(port: u16) -> std::io::Result<Self> {
+		let socket = UdpSocket::bind(("0.0.0.0", port))?;
+		socket.set_nonblocking(true)?;
+		Ok(Host {
+			socket,
+			peers: Vec::new(),
+			tick: 0,
+		})
+	}
+
+	fn receive(&mut self, now: f64, players: usize, seed: u64) {
+		for (address, message) in receive::<ClientMessage>(&self.socket) {
+			let peer = self.peers.iter().position(|peer| peer.address == address);
+			match (message, peer) {
+				(ClientMessage::Hello, Some(idx)) => {
+					// The welcome was lost.
+					self.peers[idx].last_heard = now;
+					let player = self.peers[idx].player;
+					send(
+						&self.socket,
+						address,
+						&HostMessage::Welcome { player, seed },
+					);
+				}
+				(ClientMessage::Hello, None) => {
+					// The host plays the first player.
+					let free = (1..players)
+						.find(|player| self.peers.iter().all(|peer| peer.player != *player));
+					let player = match free {
+						Some(player) => player,
+						None => {
+							send(&self.socket, address, &HostMessage::Full);
+							continue;
+						}
+					};
+					info!("{} joined as player {}", address, player + 1);
+					self.peers.push(Peer {
+						address,
+						player,
+						last_heard: now,
+						received: None,
+						applied: None,
+						queue: VecDeque::new(),
+					});
+					send(
+						&self.socket,
+						address,
+						&HostMessage::Welcome { player, seed },
+					);
+				}
+				(ClientMessage::Inputs { first, frames }, Some(idx)) => {
+					let peer = &mut self.peers[idx];
+					peer.last_heard = now;
+					for (number, frame) in (first..).zip(frames) {
+						if peer.received.map_or(false, |received| number <= received) {
+							continue;
+						}
+						match frame.parse::<InputFrame>() {
+							Ok(frame) => {
+								peer.queue.push_back((number, frame));
+								peer.received = Some(number);
+							}
+							Err(e) => warn!("Invalid input from {}: {}", address, e),
+						}
+					}
+				}
+				(ClientMessage::Inputs { .. }, None) => {
+					debug!("Ignoring inputs from {}, which didn't say hello", address)
+				}
+			}
+		}
+
+		self.peers.retain(|peer| {
+			let alive = now - peer.last_heard < TIMEOUT;
+			if !alive {
+				info!("Player {} at {} timed out", peer.player + 1, peer.address);
+			}
+			alive
+		});
+	}
+
+	/// Sends the snapshot to every client, acknowledging the inputs applied for it.
+	fn send_snapshot(&self, mut snapshot: Snapshot) {
+		for peer in self.peers.iter() {
+			snapshot.ack = peer.applied;
+			send(
+				&self.socket,
+				peer.address,
+				&HostMessage::Snapshot(snapshot.clone()),
+			);
+		}
+	}
+}
+
+impl Peer {
+	/// Folds the queued inputs into one, lasting as long as the client played them for, so that
+	/// the player ends up where the client predicted. Keeps the latest movement, and a dash or a
+	/// shot from any of the inputs. The player stands still while no input arrives.
+	fn next_input(&mut self) -> InputFrame {
+		let mut next = InputFrame::default();
+		while let Some((number, frame)) = self.queue.pop_front() {
+			if next.delta > 0.0 && next.delta + frame.delta > MAX_STEP {
+				self.queue.push_front((number, frame));
+				break;
+			}
+			next = InputFrame {
+				delta: next.delta + frame.delta,
+				dash: next.dash || frame.dash,
+				fire: frame.fire.or(next.fire),
+				..frame
+			};
+			self.applied = Some(number);
+		}
+		next
+	}
+}
+
+/// Plays one of the players of a game hosted by another instance.
+pub struct Client {
+	socket: UdpSocket,
+	host: SocketAddr,
+	/// Player given by the host, `None` until it answers.
+	player: Option<usize>,
+	/// Seconds since startup.
+	last_hello: f64,
+	last_heard: f64,
+	next_input: u32,
+	/// Inputs the host didn't acknowledge yet, oldest first.
+	history: VecDeque<Prediction>,
+	/// The newest snapshot, until it's applied.
+	snapshot: Option<Snapshot>,
+	tick: u64,
+	/// Level and dungeon depth of the last applied snapshot.
+	level: Option<(LevelType, u64)>,
+	/// Entities of the host's missiles.
+	missiles: HashMap<u64, Entity>,
+}
+
+/// An input sent to the host and where the local player was before applying it.
+struct Prediction {
+	number: u32,
+	frame: String,
+	before: Vec2,
+}
+
+impl Client {
+	fn bind(host: SocketAddr) -> std::io::Result<Self> {
+		let socket = UdpSocket::bind(if host.is_ipv4() {
+			"0.0.0.0:0"
+		} else {
+			"[::]:0"
+		})?;
+		socket.set_nonblocking(true)?;
+		Ok(Client {
+			socket,
+			host,
+			player: None,
+			last_hello: f64::NEG_INFINITY,
+			last_heard: 0.0,
+			next_input: 0,
+			history: VecDeque::new(),
+			snapshot: None,
+			tick: 0,
+			level: None,
+			missiles: HashMap::default(),
+		})
+	}
+
+	fn receive(&mut self, now: f64, dungeon: &mut Dungeon) {
+		for (address, message) in receive::<HostMessage>(&self.socket) {
+			if address != self.host {
+				debug!("Ignoring a packet from {}", address);
+				continue;
+			}
+			self.last_heard = now;
+			match message {
+				HostMessage::Welcome { player, seed } => {
+					if self.player.is_none() {
+						info!("Joined {} as player {}", self.host, player + 1);
+						self.tick = 0;
+						// Spawn the level again, with the host's dungeon.
+						self.level = None;
+					}
+					self.player = Some(player);
+					dungeon.seed = seed;
+				}
+				HostMessage::Full => warn!("{} has no free player", self.host),
+				HostMessage::Snapshot(snapshot) => {
+					if self.player.is_some() && snapshot.tick > self.tick {
+						self.tick = snapshot.tick;
+						self.snapshot = Some(snapshot);
+					}
+				}
+			}
+		}
+
+		if self.player.is_some() && now - self.last_heard > TIMEOUT {
+			warn!("Lost connection to {}", self.host);
+			self.player = None;
+		}
+		if self.player.is_none() && now - self.last_hello > HELLO_INTERVAL {
+			self.last_hello = now;
+			send(&self.socket, self.host, &ClientMessage::Hello);
+		}
+	}
+
+	/// Forgets the inputs up to `ack` and returns where the local player was predicted to be after
+	/// it.
+	fn acknowledge(&mut self, ack: u32, current: Vec2) -> Vec2 {
+		while self
+			.history
+			.front()
+			.map_or(false, |prediction| prediction.number <= ack)
+		{
+			self.history.pop_front();
+		}
+		self.history
+			.front()
+			.map_or(current, |prediction| prediction.before)
+	}
+
+	/// Sends `frame` to the host, together with the inputs it didn't acknowledge yet. `before` is
+	/// where the local player was before applying it.
+	fn send_input(&mut self, frame: &InputFrame, before: Vec2) {
+		self.history.push_back(Prediction {
+			number: self.next_input,
+			frame: frame.to_string(),
+			before,
+		});
+		self.next_input += 1;
+		if self.history.len() > MAX_HISTORY {
+			self.history.pop_front();
+		}
+
+		let resent = self.history.len().saturating_sub(MAX_RESENT_INPUTS);
+		let first = self.history[resent].number;
+		let frames = self
+			.history
+			.iter()
+			.skip(resent)
+			.map(|prediction| prediction.frame.clone())
+			.collect();
+		send(
+			&self.socket,
+			self.host,
+			&ClientMessage::Inputs { first, frames },
+		);
+	}
+}
+
+/// Player indices are swapped on a client, so that its own player is the first one and everything
+/// following the first player (the camera, the crosshair, the HUD) follows it.
+fn local_index(player: usize, me: usize) -> usize {
+	if player == me {
+		0
+	} else if player == 0 {
+		me
+	} else {
+		player
+	}
+}
+
+fn send(socket: &UdpSocket, address: SocketAddr, message: &impl Serialize) {
+	let packet = match serde_json::to_vec(message) {
+		Ok(packet) => packet,
+		Err(e) => {
+			error!("Failed to serialize a packet: {}", e);
+			return;
+		}
+	};
+	// Not worth a warning every frame, the timeouts tell when the other side is gone.
+	if let Err(e) = socket.send_to(&packet, address) {
+		debug!("Failed to send a packet to {}: {}", address, e);
+	}
+}
+
+/// Reads every packet waiting on the socket.
+fn receive<T: DeserializeOwned>(socket: &UdpSocket) -> Vec<(SocketAddr, T)> {
+	let mut buffer = vec![0; MAX_PACKET_SIZE];
+	let mut messages = Vec::new();
+	loop {
+		match socket.recv_from(&mut buffer) {
+			Ok((len, address)) => match serde_json::from_slice(&buffer[..len]) {
+				Ok(message) => messages.push((address, message)),
+				Err(e) => warn!("Invalid packet from {}: {}", address, e),
+			},
+			Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+			// Some platforms report packets the other side didn't receive.
+			Err(e) => {
+				debug!("Failed to receive a packet: {}", e);
+				break;
+			}
+		}
+	}
+	messages
+}
+
+fn receive_packets(
+	time: Res<Time>,
+	settings: Res<Settings>,
+	mut net: ResMut<NetMode>,
+	mut dungeon: ResMut<Dungeon>,
+) {
+	let now = time.seconds_since_startup();
+	match &mut *net {
+		NetMode::Offline => {}
+		NetMode::Host(host) => host.receive(now, settings.players, dungeon.seed),
+		NetMode::Client(client) => client.receive(now, &mut dungeon),
+	}
+}
+
+/// On the host, replaces the input of the remote players with the one received from their
+/// clients. On a client, sends the local input to the host.
+fn exchange_input(
+	mut net: ResMut<NetMode>,
+	mut frames: ResMut<InputFrames>,
+	players: Query<(&Player, &Transform)>,
+) {
+	// Players without input still take the local time step.
+	let delta = frames.get(0).map_or(0.0, |frame| frame.delta);
+	match &mut *net {
+		NetMode::Offline => {}
+		NetMode::Host(host) => {
+			for (player, frame) in frames.0.iter_mut().enumerate().skip(1) {
+				*frame = match host.peers.iter_mut().find(|peer| peer.player == player) {
+					Some(peer) => peer.next_input(),
+					None => InputFrame {
+						delta,
+						..Default::default()
+					},
+				};
+			}
+		}
+		NetMode::Client(client) => {
+			// The host moves the other players.
+			for frame in frames.0.iter_mut().skip(1) {
+				*frame = InputFrame {
+					delta,
+					..Default::default()
+				};
+			}
+			let frame = match (client.player, frames.get(0)) {
+				(Some(_), Some(frame)) => frame,
+				_ => return,
+			};
+			let before = players
+				.iter()
+				.find(|(Player(idx), _)| *idx == 0)
+				.map_or(Vec2::ZERO, |(_, transform)| transform.translation.xy());
+			client.send_input(frame, before);
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_snapshot(
+	mut commands: Commands,
+	mut net: ResMut<NetMode>,
+	textures: Res<Textures>,
+	mut level: ResMut<Level>,
+	mut dungeon: ResMut<Dungeon>,
+	mut spawner: ResMut<LevelSpawner>,
+	handles: Res<LevelHandles>,
+	mut levels: ResMut<Assets<LevelData>>,
+	mut score: ResMut<Score>,
+	mut state: ResMut<State<AppState>>,
+	mut explosion_events: EventWriter<ExplosionEvent>,
+	mut players: Query<(Entity, &Player, &mut Transform, &mut Health)>,
+	mut missiles: Query<&mut Transform, (With<Missile>, Without<Player>)>,
+	enemies: Query<(Entity, &Transform), (With<Enemy>, Without<Player>, Without<Missile>)>,
+) {
+	let client = match &mut *net {
+		NetMode::Client(client) => client,
+		_ => return,
+	};
+	let (me, snapshot) = match (client.player, client.snapshot.take()) {
+		(Some(me), Some(snapshot)) => (me, snapshot),
+		_ => return,
+	};
+
+	// Follow the host to other levels and dungeon floors.
+	let entered = client.level != Some((snapshot.level, snapshot.depth));
+	if entered {
+		client.level = Some((snapshot.level, snapshot.depth));
+		dungeon.depth = snapshot.depth;
+		if snapshot.level == LevelType::Secret1 {
+			if let Some(handle) = handles.0.get(&LevelType::Secret1) {
+				levels.set(handle.clone(), dungeon.generate());
+			}
+		}
+		info!("Following the host to {:?}", snapshot.level);
+		level.l_type = snapshot.level;
+		spawner.enter();
+	}
+
+	if score.0 != snapshot.score {
+		score.0 = snapshot.score;
+	}
+	if snapshot.game_over && state.set(AppState::GameOver).is_ok() {
+		warn!("Game over");
+	}
+
+	for (entity, Player(idx), mut transform, mut health) in players.iter_mut() {
+		let player_state = match snapshot
+			.players
+			.iter()
+			.find(|player_state| local_index(player_state.player, me) == *idx)
+		{
+			Some(player_state) => player_state,
+			None => {
+				// Out of the game on the host.
+				commands.entity(entity).despawn_recursive();
+				continue;
+			}
+		};
+		if health.current != player_state.health {
+			health.current = player_state.health;
+		}
+		let position = Vec2::from(player_state.position);
+		if *idx != 0 {
+			transform.translation.x = position.x;
+			transform.translation.y = position.y;
+			continue;
+		}
+		// The local player is predicted, so only correct it by how far off the prediction was at
+		// the last input the host applied.
+		let ack = match snapshot.ack {
+			Some(ack) => ack,
+			None => continue,
+		};
+		let error = position - client.acknowledge(ack, transform.translation.xy());
+		if error.length() > RECONCILE_THRESHOLD {
+			debug!("Correcting the prediction by {:?}", error);
+			transform.translation += error.extend(0.0);
+			for prediction in client.history.iter_mut() {
+				prediction.before += error;
+			}
+		}
+	}
+
+	for missile in snapshot.missiles.iter() {
+		let position = Vec3::from(missile.position);
+		let direction = Vec2::from(missile.direction);
+		let existing = client
+			.missiles
+			.get(&missile.id)
+			.and_then(|entity| missiles.get_mut(*entity).ok());
+		if let Some(mut transform) = existing {
+			transform.translation = position;
+			continue;
+		}
+		let mut entity = commands.spawn_bundle(SpriteBundle {
+			material: textures.missile_texture.clone(),
+			transform: Transform {
+				translation: position,
+				// The missile sprite is facing up.
+				rotation: Quat::from_rotation_z(direction.y.atan2(direction.x) - FRAC_PI_2),
+				scale: Vec3::ONE,
+			},
+			..Default::default()
+		});
+		// The host decides what the missiles hit, so the owner doesn't matter here.
+		let owner = entity.id();
+		entity
+			.insert(Missile {
+				owner,
+				direction: direction.extend(0.0),
+				speed: Vec3::ONE,
+			})
+			.insert(StateScoped(AppState::Game));
+		client.missiles.insert(missile.id, owner);
+	}
+	// Missing missiles hit something, or left the level.
+	let (min, max) = level.bounds();
+	client.missiles.retain(|id, entity| {
+		if snapshot.missiles.iter().any(|missile| missile.id == *id) {
+			return true;
+		}
+		if let Ok(transform) = missiles.get_mut(*entity) {
+			let position = transform.translation;
+			if position.xy().clamp(min, max) == position.xy() {
+				explosion_events.send(ExplosionEvent { position });
+			}
+			commands.entity(*entity).despawn_recursive();
+		}
+		false
+	});
+
+	// The old level is being despawned anyway.
+	if !entered {
+		for (entity, transform) in enemies.iter() {
+			let position = transform.translation.xy();
+			let alive = snapshot
+				.enemies
+				.iter()
+				.any(|enemy| Vec2::from(*enemy).distance(position) < TILE_SIZE / 2.0);
+			if !alive {
+				commands.entity(entity).despawn_recursive();
+			}
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_snapshots(
+	mut net: ResMut<NetMode>,
+	state: Res<State<AppState>>,
+	level: Res<Level>,
+	dungeon: Res<Dungeon>,
+	score: Res<Score>,
+	players: Query<(&Player, &Transform, &Health)>,
+	missiles: Query<(Entity, &Transform, &Missile)>,
+	enemies: Query<&Transform, With<Enemy>>,
+) {
+	let host = match &mut *net {
+		NetMode::Host(host) if !host.peers.is_empty() => host,
+		_ => return,
+	};
+	host.tick += 1;
+	let (min, max) = level.bounds();
+	let snapshot = Snapshot {
+		tick: host.tick,
+		ack: None,
+		level: level.l_type,
+		depth: dungeon.depth,
+		score: score.0,
+		game_over: *state.current() == AppState::GameOver,
+		players: players
+			.iter()
+			.map(|(Player(player), transform, health)| PlayerState {
+				player: *player,
+				position: transform.translation.xy().into(),
+				health: health.current,
+			})
+			.collect(),
+		missiles: missiles
+			.iter()
+			.filter(|(_, transform, _)| {
+				transform.translation.xy().clamp(min, max) == transform.translation.xy()
+			})
+			.map(|(entity, transform, missile)| MissileState {
+				id: entity.to_bits(),
+				position: transform.translation.into(),
+				direction: missile.direction.xy().into(),
+			})
+			.collect(),
+		enemies: enemies
+			.iter()
+			.map(|transform| transform.translation.xy().into())
+			.collect(),
+	};
+	host.send_snapshot(snapshot);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	/// Polls until `done` returns true, giving the packets time to arrive over localhost.
+	fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+		for _ in 0..200 {
+			if done() {
+				return;
+			}
+			std::thread::sleep(Duration::from_millis(5));
+		}
+		panic!("timed out waiting for {}", what);
+	}
+
+	fn connect(players: usize, seed: u64) -> (Host, Client, Dungeon) {
+		let mut host = Host::bind(0).unwrap();
+		let port = host.socket.local_addr().unwrap().port();
+		let mut client = Client::bind(SocketAddr::from(([127, 0, 0, 1], port))).unwrap();
+		let mut dungeon = Dungeon { seed: 0, depth: 0 };
+		// Says hello on the first call.
+		client.receive(0.0, &mut dungeon);
+		wait_for("the hello", || {
+			host.receive(0.0, players, seed);
+			!host.peers.is_empty()
+		});
+		wait_for("the welcome", || {
+			client.receive(0.0, &mut dungeon);
+			client.player.is_some()
+		});
+		(host, client, dungeon)
+	}
+
+	fn moving_right(delta: f32) -> InputFrame {
+		InputFrame {
+			delta,
+			right: true,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn clients_join_as_the_next_player() {
+		let (host, client, dungeon) = connect(MAX_PLAYERS, 42);
+		assert_eq!(client.player, Some(1));
+		assert_eq!(host.peers[0].player, 1);
+		assert_eq!(dungeon.seed, 42);
+
+		// The host plays the first player, so a single player game is full.
+		let mut host = Host::bind(0).unwrap();
+		let port = host.socket.local_addr().unwrap().port();
+		let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+		client.set_nonblocking(true).unwrap();
+		send(
+			&client,
+			SocketAddr::from(([127, 0, 0, 1], port)),
+			&ClientMessage::Hello,
+		);
+		let mut full = false;
+		wait_for("the host to answer", || {
+			host.receive(0.0, 1, 42);
+			full |= receive::<HostMessage>(&client)
+				.into_iter()
+				.any(|(_, message)| matches!(message, HostMessage::Full));
+			full
+		});
+		assert!(host.peers.is_empty());
+	}
+
+	#[test]
+	fn host_simulates_as_long_as_the_client_predicted() {
+		let (mut host, mut client, mut dungeon) = connect(MAX_PLAYERS, 0);
+
+		// The client runs at twice the host's frame rate.
+		client.send_input(&moving_right(0.008), Vec2::ZERO);
+		client.send_input(&moving_right(0.008), Vec2::new(0.8, 0.0));
+		wait_for("the inputs", || {
+			host.receive(0.0, MAX_PLAYERS, 0);
+			host.peers[0].received == Some(1)
+		});
+		let frame = host.peers[0].next_input();
+		assert!((frame.delta - 0.016).abs() < 1e-6);
+		assert!(frame.right);
+		assert_eq!(host.peers[0].applied, Some(1));
+
+		// Without new inputs the player waits for them instead of running ahead of the client.
+		let idle = host.peers[0].next_input();
+		assert_eq!(idle.delta, 0.0);
+		assert_eq!(idle.movement(), Vec2::ZERO);
+		assert_eq!(host.peers[0].applied, Some(1));
+
+		client.send_input(&moving_right(0.008), Vec2::new(1.6, 0.0));
+		host.send_snapshot(Snapshot {
+			tick: 1,
+			ack: None,
+			level: LevelType::Hub,
+			depth: 0,
+			score: 0,
+			game_over: false,
+			players: Vec::new(),
+			missiles: Vec::new(),
+			enemies: Vec::new(),
+		});
+		wait_for("the snapshot", || {
+			client.receive(0.0, &mut dungeon);
+			client.snapshot.is_some()
+		});
+		assert_eq!(client.snapshot.as_ref().unwrap().ack, Some(1));
+		// The host applied the first two inputs, so the prediction is checked against where the
+		// player was before the third one.
+		assert_eq!(
+			client.acknowledge(1, Vec2::new(2.4, 0.0)),
+			Vec2::new(1.6, 0.0)
+		);
+		assert_eq!(client.history.len(), 1);
+	}
+
+	#[test]
+	fn bursts_of_inputs_are_spread_over_frames() {
+		let (mut host, mut client, _) = connect(MAX_PLAYERS, 0);
+		let count = (2.5 * MAX_STEP / 0.016) as u32;
+		for _ in 0..count {
+			client.send_input(&moving_right(0.016), Vec2::ZERO);
+		}
+		wait_for("the inputs", || {
+			host.receive(0.0, MAX_PLAYERS, 0);
+			host.peers[0].received == Some(count - 1)
+		});
+		let mut total = 0.0;
+		while host.peers[0].applied != Some(count - 1) {
+			let frame = host.peers[0].next_input();
+			assert!(frame.delta > 0.0 && frame.delta <= MAX_STEP);
+			total += frame.delta;
+		}
+		assert!((total - count as f32 * 0.016).abs() < 1e-4);
+	}
+}
@@ -0,0 +1,106 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_asset::<PlayerConfig>()
+			.init_asset_loader::<PlayerConfigLoader>()
+			.add_startup_system(load_player_config.system());
+	}
+}
+
+/// Player movement tuning, loaded from `config/player.cfg`.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "7d3b8a52-4f0e-4c1b-9a57-2f6c0e8d1b34"]
+pub struct PlayerConfig {
+	/// Pixels per second.
+	pub max_speed: f32,
+	/// Pixels per second squared, applied while a movement key is pressed.
+	pub acceleration: f32,
+	/// Pixels per second squared, applied while no movement key is pressed.
+	pub friction: f32,
+	/// Pixels per second.
+	pub dash_speed: f32,
+	/// Seconds.
+	pub dash_duration: f32,
+	/// Seconds between the starts of two dashes.
+	pub dash_cooldown: f32,
+	/// Seconds the player can't be hurt after starting a dash.
+	pub dash_invulnerability: f32,
+}
+
+impl Default for PlayerConfig {
+	fn default() -> Self {
+		PlayerConfig {
+			max_speed: 100.0,
+			acceleration: 800.0,
+			friction: 1000.0,
+			dash_speed: 350.0,
+			dash_duration: 0.15,
+			dash_cooldown: 0.8,
+			dash_invulnerability: 0.25,
+		}
+	}
+}
+
+pub struct PlayerConfigHandle(pub Handle<PlayerConfig>);
+
+impl PlayerConfigHandle {
+	/// Returns the loaded config, or the defaults while it's still loading.
+	pub fn get(&self, configs: &Assets<PlayerConfig>) -> PlayerConfig {
+		configs.get(&self.0).cloned().unwrap_or_default()
+	}
+}
+
+#[derive(Default)]
+pub struct PlayerConfigLoader;
+
+impl AssetLoader for PlayerConfigLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let config = ron::de::from_bytes::<PlayerConfig>(bytes)?;
+			load_context.set_default_asset(LoadedAsset::new(config));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["cfg"]
+	}
+}
+
+/// Current velocity in pixels per second.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Velocity(pub Vec2);
+
+/// Dash state of the player.
+#[derive(Debug, Default)]
+pub struct Dash {
+	/// Time left until the next dash can start.
+	pub cooldown: f32,
+	/// Time left of the current dash.
+	pub remaining: f32,
+}
+
+fn load_player_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+	commands.insert_resource(PlayerConfigHandle(asset_server.load("config/player.cfg")));
+}
+
+/// Moves `velocity` toward `target` by at most `max_delta`.
+pub fn approach(velocity: Vec2, target: Vec2, max_delta: f32) -> Vec2 {
+	let diff = target - velocity;
+	if diff.length() <= max_delta {
+		target
+	} else {
+		velocity + diff.normalize() * max_delta
+	}
+}
@@ -0,0 +1,503 @@
+use crate::cli::Args;
+use crate::components::{AimDirection, Player};
+use crate::crosshair::{Aim, AimLabel, GAMEPAD_AIM_DEAD_ZONE, GAMEPAD_AIM_DISTANCE};
+use crate::effects::TimeScale;
+use crate::settings::Settings;
+use crate::touch::TouchControls;
+use crate::AppState;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// v2 lines hold the frames of every player separated by [`PLAYER_SEPARATOR`]. v1 lines are
+/// read as a single player.
+static RECORDING_HEADER: &str = "# kod_jam input recording v2";
+static PLAYER_SEPARATOR: &str = " | ";
+/// Left stick or touch joystick deflection that counts as pressing a direction.
+const STICK_THRESHOLD: f32 = 0.5;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.insert_resource(ReplayMode::Live)
+			.init_resource::<InputFrames>()
+			.init_resource::<ActiveGamepad>()
+			.init_resource::<PlayerControls>()
+			.add_startup_system(setup_replay.system())
+			.add_system(track_gamepad.system())
+			.add_system_set(
+				SystemSet::on_update(AppState::Game).with_system(
+					sample_input
+						.system()
+						.label(SampleLabel)
+						.label(InputLabel)
+						.after(AimLabel),
+				),
+			);
+	}
+}
+
+/// Systems reading [`InputFrames`] have to run after this label.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InputLabel;
+
+/// Sampling the input devices. Systems replacing the sampled frames of some players, like the
+/// network input, run after it with the [`InputLabel`].
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SampleLabel;
+
+/// The gamepad the player uses, if any is connected.
+#[derive(Debug, Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+/// Devices a player is controlled with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Controls {
+	/// WASD, left shift to dash, and the mouse, space or the gamepad trigger to fire at the
+	/// crosshair. The gamepad is only used when playing alone. Also the touch joystick, with taps
+	/// firing where they land.
+	Primary(Option<Gamepad>),
+	/// Arrow keys, right shift to dash and right control to fire in the aim direction.
+	Arrows,
+	/// Left stick or d-pad, south button to dash, right trigger to fire and the right stick to aim.
+	Gamepad(Gamepad),
+}
+
+/// Controls of every player, indexed by [`Player`] index.
+#[derive(Debug)]
+pub struct PlayerControls(pub Vec<Controls>);
+
+impl PlayerControls {
+	/// The second player gets the gamepad if there is one.
+	fn new(players: usize, gamepad: Option<Gamepad>) -> Self {
+		let mut controls = vec![Controls::Primary(gamepad.filter(|_| players == 1))];
+		if players > 1 {
+			controls.push(gamepad.map_or(Controls::Arrows, Controls::Gamepad));
+		}
+		PlayerControls(controls)
+	}
+}
+
+impl FromWorld for PlayerControls {
+	fn from_world(world: &mut World) -> Self {
+		let players = world
+			.get_resource::<Settings>()
+			.map_or(1, |settings| settings.players);
+		PlayerControls::new(players, None)
+	}
+}
+
+/// Player input for a single game tick, either sampled from the devices or read from a recording.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InputFrame {
+	/// Time step the frame was sampled with, so that playback moves the player by the same amount.
+	pub delta: f32,
+	pub up: bool,
+	pub left: bool,
+	pub down: bool,
+	pub right: bool,
+	pub dash: bool,
+	/// Cursor position in the world coordinates if the player fired this tick.
+	pub fire: Option<Vec2>,
+}
+
+impl InputFrame {
+	/// Normalized, so that moving diagonally isn't faster.
+	pub fn movement(&self) -> Vec2 {
+		Vec2::new(
+			self.right as i32 as f32 - self.left as i32 as f32,
+			self.up as i32 as f32 - self.down as i32 as f32,
+		)
+		.normalize_or_zero()
+	}
+}
+
+/// Input of every player for the current tick, indexed by [`Player`] index.
+#[derive(Debug, Default)]
+pub struct InputFrames(pub Vec<InputFrame>);
+
+impl InputFrames {
+	/// `None` for players missing from a recording.
+	pub fn get(&self, player: usize) -> Option<&InputFrame> {
+		self.0.get(player)
+	}
+}
+
+impl fmt::Display for InputFrame {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let key = |pressed: bool, c: char| if pressed { c } else { '.' };
+		write!(
+			f,
+			"{} {}{}{}{}",
+			self.delta,
+			key(self.up, 'W'),
+			key(self.left, 'A'),
+			key(self.down, 'S'),
+			key(self.right, 'D'),
+		)?;
+		if self.dash {
+			write!(f, " dash")?;
+		}
+		if let Some(target) = self.fire {
+			write!(f, " fire {} {}", target.x, target.y)?;
+		}
+		Ok(())
+	}
+}
+
+impl FromStr for InputFrame {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut fields = s.split_whitespace();
+		let delta = fields
+			.next()
+			.ok_or("missing time delta")?
+			.parse::<f32>()
+			.map_err(|e| format!("invalid time delta: {}", e))?;
+		let keys = fields.next().ok_or("missing movement keys")?.as_bytes();
+		if keys.len() != 4 {
+			return Err(format!("expected 4 movement keys, got {}", keys.len()));
+		}
+		let mut frame = InputFrame {
+			delta,
+			up: keys[0] == b'W',
+			left: keys[1] == b'A',
+			down: keys[2] == b'S',
+			right: keys[3] == b'D',
+			..Default::default()
+		};
+		while let Some(field) = fields.next() {
+			match field {
+				"dash" => frame.dash = true,
+				"fire" => {
+					let mut coord = || -> Result<f32, String> {
+						fields
+							.next()
+							.ok_or("missing fire target")?
+							.parse::<f32>()
+							.map_err(|e| format!("invalid fire target: {}", e))
+					};
+					frame.fire = Some(Vec2::new(coord()?, coord()?));
+				}
+				field => return Err(format!("unexpected field {:?}", field)),
+			}
+		}
+		Ok(frame)
+	}
+}
+
+pub enum ReplayMode {
+	Live,
+	/// Sampled frames are also appended to a recording file.
+	Recording(LineWriter<File>),
+	/// Frames come from a recording instead of the input devices.
+	Playback(VecDeque<Vec<InputFrame>>),
+}
+
+fn setup_replay(args: Res<Args>, mut mode: ResMut<ReplayMode>) {
+	if let Some(path) = &args.replay {
+		match load_recording(path) {
+			Ok(frames) => {
+				info!("Replaying {} frames from {}", frames.len(), path.display());
+				*mode = ReplayMode::Playback(frames);
+			}
+			Err(e) => error!("Failed to load recording {}: {}", path.display(), e),
+		}
+	} else if let Some(path) = &args.record {
+		match File::create(path).map(LineWriter::new) {
+			Ok(mut file) => match writeln!(file, "{}", RECORDING_HEADER) {
+				Ok(()) => {
+					info!("Recording input to {}", path.display());
+					*mode = ReplayMode::Recording(file);
+				}
+				Err(e) => error!("Failed to write recording {}: {}", path.display(), e),
+			},
+			Err(e) => error!("Failed to create recording {}: {}", path.display(), e),
+		}
+	}
+}
+
+fn load_recording(path: &Path) -> Result<VecDeque<Vec<InputFrame>>, String> {
+	let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+	parse_recording(&contents)
+}
+
+/// Reads both v1 recordings, with one player per line, and v2 ones with every player's frame.
+fn parse_recording(contents: &str) -> Result<VecDeque<Vec<InputFrame>>, String> {
+	contents
+		.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+		.map(|(idx, line)| {
+			line.split(PLAYER_SEPARATOR)
+				.map(str::parse::<InputFrame>)
+				.collect::<Result<Vec<_>, _>>()
+				.map_err(|e| format!("line {}: {}", idx + 1, e))
+		})
+		.collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sample_input(
+	time: Res<Time>,
+	time_scale: Res<TimeScale>,
+	args: Res<Args>,
+	kb_input: Res<Input<KeyCode>>,
+	mouse_input: Res<Input<MouseButton>>,
+	gamepad_input: Res<Input<GamepadButton>>,
+	axes: Res<Axis<GamepadAxis>>,
+	controls: Res<PlayerControls>,
+	touch: Res<TouchControls>,
+	aim: Res<Aim>,
+	mut mode: ResMut<ReplayMode>,
+	mut frames: ResMut<InputFrames>,
+	mut exit_signal: EventWriter<AppExit>,
+	mut players: Query<(&Player, &Transform, &mut AimDirection)>,
+) {
+	if let ReplayMode::Playback(recorded) = &mut *mode {
+		match recorded.pop_front() {
+			Some(next) => frames.0 = next,
+			None => {
+				if args.headless {
+					info!("Replay finished");
+					exit_signal.send(AppExit);
+				} else {
+					info!("Replay finished, switching to live input");
+				}
+				*mode = ReplayMode::Live;
+			}
+		}
+		if let ReplayMode::Playback(_) = *mode {
+			return;
+		}
+	}
+
+	let delta = time.delta_seconds() * time_scale.0;
+	frames.0.clear();
+	for (idx, controls) in controls.0.iter().enumerate() {
+		let mut frame = InputFrame {
+			delta,
+			..Default::default()
+		};
+		let (fired, aim_stick) = match *controls {
+			Controls::Primary(gamepad) => {
+				let stick = touch.movement;
+				frame.up = kb_input.pressed(KeyCode::W) || stick.y > STICK_THRESHOLD;
+				frame.left = kb_input.pressed(KeyCode::A) || stick.x < -STICK_THRESHOLD;
+				frame.down = kb_input.pressed(KeyCode::S) || stick.y < -STICK_THRESHOLD;
+				frame.right = kb_input.pressed(KeyCode::D) || stick.x > STICK_THRESHOLD;
+				frame.dash = kb_input.just_pressed(KeyCode::LShift);
+
+				let gamepad_fire = gamepad.map_or(false, |gamepad| {
+					gamepad_input
+						.just_pressed(GamepadButton(gamepad, GamepadButtonType::RightTrigger2))
+				});
+				// Browsers follow a tap with a click, which would fire at the joystick.
+				let clicked = !touch.enabled && mouse_input.just_pressed(MouseButton::Left);
+				if clicked || kb_input.just_pressed(KeyCode::Space) || gamepad_fire {
+					match aim.position {
+						Some(position) => {
+							debug!("World coords: {:?}", position);
+							frame.fire = Some(position);
+						}
+						None => error!("Can't fire without an aim position"),
+					}
+				}
+				if let Some(target) = touch.fire {
+					frame.fire = Some(target);
+				}
+				// Aims with the crosshair.
+				frames.0.push(frame);
+				continue;
+			}
+			Controls::Arrows => {
+				frame.up = kb_input.pressed(KeyCode::Up);
+				frame.left = kb_input.pressed(KeyCode::Left);
+				frame.down = kb_input.pressed(KeyCode::Down);
+				frame.right = kb_input.pressed(KeyCode::Right);
+				frame.dash = kb_input.just_pressed(KeyCode::RShift);
+				(kb_input.just_pressed(KeyCode::RControl), Vec2::ZERO)
+			}
+			Controls::Gamepad(gamepad) => {
+				let axis = |axis_type| axes.get(GamepadAxis(gamepad, axis_type)).unwrap_or(0.0);
+				let button =
+					|button_type| gamepad_input.pressed(GamepadButton(gamepad, button_type));
+				let stick = Vec2::new(
+					axis(GamepadAxisType::LeftStickX),
+					axis(GamepadAxisType::LeftStickY),
+				);
+				frame.up = stick.y > STICK_THRESHOLD || button(GamepadButtonType::DPadUp);
+				frame.left = stick.x < -STICK_THRESHOLD || button(GamepadButtonType::DPadLeft);
+				frame.down = stick.y < -STICK_THRESHOLD || button(GamepadButtonType::DPadDown);
+				frame.right = stick.x > STICK_THRESHOLD || button(GamepadButtonType::DPadRight);
+				frame.dash =
+					gamepad_input.just_pressed(GamepadButton(gamepad, GamepadButtonType::South));
+				let fired = gamepad_input
+					.just_pressed(GamepadButton(gamepad, GamepadButtonType::RightTrigger2));
+				let aim_stick = Vec2::new(
+					axis(GamepadAxisType::RightStickX),
+					axis(GamepadAxisType::RightStickY),
+				);
+				(fired, aim_stick)
+			}
+		};
+
+		// Aim with the right stick, or where the player last moved.
+		if let Some((_, transform, mut aim_direction)) = players
+			.iter_mut()
+			.find(|(Player(player), ..)| *player == idx)
+		{
+			if aim_stick.length() > GAMEPAD_AIM_DEAD_ZONE {
+				aim_direction.0 = aim_stick.normalize();
+			} else if frame.movement() != Vec2::ZERO {
+				aim_direction.0 = frame.movement();
+			}
+			if fired {
+				frame.fire =
+					Some(transform.translation.truncate() + aim_direction.0 * GAMEPAD_AIM_DISTANCE);
+			}
+		}
+		frames.0.push(frame);
+	}
+
+	if let ReplayMode::Recording(file) = &mut *mode {
+		let line = frames
+			.0
+			.iter()
+			.map(InputFrame::to_string)
+			.collect::<Vec<_>>()
+			.join(PLAYER_SEPARATOR);
+		if let Err(e) = writeln!(file, "{}", line) {
+			error!("Failed to write input recording: {}", e);
+			*mode = ReplayMode::Live;
+		}
+	}
+}
+
+fn track_gamepad(
+	settings: Res<Settings>,
+	mut events: EventReader<GamepadEvent>,
+	mut active: ResMut<ActiveGamepad>,
+	mut controls: ResMut<PlayerControls>,
+) {
+	for GamepadEvent(gamepad, event_type) in events.iter() {
+		match event_type {
+			GamepadEventType::Connected => {
+				info!("{:?} connected", gamepad);
+				active.0.get_or_insert(*gamepad);
+			}
+			GamepadEventType::Disconnected if active.0 == Some(*gamepad) => {
+				info!("{:?} disconnected", gamepad);
+				active.0 = None;
+			}
+			_ => {}
+		}
+	}
+	if active.is_changed() {
+		*controls = PlayerControls::new(settings.players, active.0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn frames_survive_the_recording_format() {
+		let frames = [
+			InputFrame {
+				delta: 0.016,
+				up: true,
+				right: true,
+				dash: true,
+				fire: Some(Vec2::new(12.5, -3.0)),
+				..Default::default()
+			},
+			InputFrame {
+				delta: 0.5,
+				left: true,
+				down: true,
+				..Default::default()
+			},
+			InputFrame::default(),
+		];
+		for frame in frames.iter() {
+			assert_eq!(frame.to_string().parse::<InputFrame>().as_ref(), Ok(frame));
+		}
+		assert_eq!(frames[0].to_string(), "0.016 W..D dash fire 12.5 -3");
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn invalid_frames_are_rejected() {
+		for line in [
+			"",
+			"fast W...",
+			"0.016",
+			"0.016 WA",
+			"0.016 W... jump",
+			"0.016 W... fire 1",
+			"0.016 W... fire 1 up",
+		]
+		.iter()
+		{
+			assert!(
+				line.parse::<InputFrame>().is_err(),
+				"{:?} was accepted",
+				line
+			);
+		}
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn diagonal_movement_isnt_faster() {
+		let frame = InputFrame {
+			up: true,
+			left: true,
+			..Default::default()
+		};
+		assert!((frame.movement().length() - 1.0).abs() < 1e-6);
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn v1_recordings_have_one_player() {
+		let recording = parse_recording("0.016 W...\n\n0.016 ...D fire 1 2\n").unwrap();
+		assert_eq!(recording.len(), 2);
+		assert_eq!(recording[0].len(), 1);
+		assert!(recording[0][0].up);
+		assert_eq!(recording[1][0].fire, Some(Vec2::new(1.0, 2.0)));
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn v2_recordings_have_every_player() {
+		let contents = format!(
+			"{}\n0.016 W...{}0.016 ...D dash\n0.016 ....{}0.016 ....\n",
+			RECORDING_HEADER, PLAYER_SEPARATOR, PLAYER_SEPARATOR
+		);
+		let recording = parse_recording(&contents).unwrap();
+		assert_eq!(recording.len(), 2);
+		assert_eq!(recording[0].len(), 2);
+		assert!(recording[0][0].up);
+		assert!(recording[0][1].right && recording[0][1].dash);
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn recording_errors_name_the_line() {
+		let error = parse_recording("0.016 W...\n0.016 W... oops").unwrap_err();
+		assert!(error.starts_with("line 2:"), "{}", error);
+	}
+}
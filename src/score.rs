@@ -0,0 +1,165 @@
+use crate::components::Score;
+use crate::effects::TimeScale;
+use crate::AppState;
+use bevy::prelude::*;
+
+const ENEMY_POINTS: u32 = 100;
+const LEVEL_POINTS: u32 = 500;
+/// Points lost per hit point of damage.
+const DAMAGE_PENALTY: u32 = 25;
+/// Seconds after a kill in which the next kill raises the combo.
+const COMBO_WINDOW: f32 = 2.0;
+const MAX_COMBO: u32 = 8;
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<Score>()
+			.init_resource::<Combo>()
+			.add_event::<ScoreEvent>()
+			.add_system_set(SystemSet::on_enter(AppState::Game).with_system(reset_score.system()))
+			.add_system_set(
+				SystemSet::on_update(AppState::Game).with_system(count_down_combo.system()),
+			)
+			// Not limited to the game state, so that the damage ending the game still counts.
+			.add_system(update_score.system());
+	}
+}
+
+/// Something the player did that changes the [`Score`].
+#[derive(Debug, Copy, Clone)]
+pub enum ScoreEvent {
+	EnemyKilled,
+	/// The player left a level other than the hub through a portal.
+	LevelCleared,
+	/// The player took this much damage.
+	Damaged(u32),
+}
+
+/// Multiplier of the points for quick kills in a row.
+#[derive(Debug)]
+pub struct Combo {
+	pub multiplier: u32,
+	/// Seconds left to raise the multiplier with another kill.
+	pub time_left: f32,
+}
+
+impl Default for Combo {
+	fn default() -> Self {
+		Combo {
+			multiplier: 1,
+			time_left: 0.0,
+		}
+	}
+}
+
+fn reset_score(mut score: ResMut<Score>, mut combo: ResMut<Combo>) {
+	*score = Score::default();
+	*combo = Combo::default();
+}
+
+/// Runs the combo window down with the gameplay time, so that it stands still during hit-stops
+/// and while the game is paused.
+fn count_down_combo(time: Res<Time>, time_scale: Res<TimeScale>, mut combo: ResMut<Combo>) {
+	if combo.time_left > 0.0 {
+		count_down(&mut combo, time.delta_seconds() * time_scale.0);
+	}
+}
+
+fn update_score(
+	mut events: EventReader<ScoreEvent>,
+	mut score: ResMut<Score>,
+	mut combo: ResMut<Combo>,
+) {
+	for event in events.iter() {
+		apply_score_event(&mut score, &mut combo, *event);
+		debug!(
+			"{:?}, score {}, combo x{}",
+			event, score.0, combo.multiplier
+		);
+	}
+}
+
+fn count_down(combo: &mut Combo, delta: f32) {
+	combo.time_left = (combo.time_left - delta).max(0.0);
+	if combo.time_left == 0.0 {
+		combo.multiplier = 1;
+	}
+}
+
+fn apply_score_event(score: &mut Score, combo: &mut Combo, event: ScoreEvent) {
+	match event {
+		ScoreEvent::EnemyKilled => {
+			if combo.time_left > 0.0 {
+				combo.multiplier = (combo.multiplier + 1).min(MAX_COMBO);
+			}
+			combo.time_left = COMBO_WINDOW;
+			score.0 += ENEMY_POINTS * combo.multiplier;
+		}
+		ScoreEvent::LevelCleared => score.0 += LEVEL_POINTS,
+		ScoreEvent::Damaged(damage) => {
+			*combo = Combo::default();
+			score.0 = score.0.saturating_sub(damage * DAMAGE_PENALTY);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn quick_kills_raise_the_combo_up_to_the_max() {
+		let mut score = Score::default();
+		let mut combo = Combo::default();
+		let mut expected = 0;
+		for kill in 1..=MAX_COMBO + 2 {
+			apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+			assert_eq!(combo.multiplier, kill.min(MAX_COMBO));
+			expected += ENEMY_POINTS * kill.min(MAX_COMBO);
+			assert_eq!(score.0, expected);
+			count_down(&mut combo, COMBO_WINDOW - 0.1);
+		}
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn damage_ends_the_combo_and_the_score_stays_positive() {
+		let mut score = Score::default();
+		let mut combo = Combo::default();
+		apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+		apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+		assert_eq!(score.0, 3 * ENEMY_POINTS);
+		apply_score_event(&mut score, &mut combo, ScoreEvent::Damaged(2));
+		assert_eq!(score.0, 3 * ENEMY_POINTS - 2 * DAMAGE_PENALTY);
+		assert_eq!(combo.multiplier, 1);
+		assert_eq!(combo.time_left, 0.0);
+
+		apply_score_event(&mut score, &mut combo, ScoreEvent::Damaged(100));
+		assert_eq!(score.0, 0);
+		apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+		assert_eq!(score.0, ENEMY_POINTS);
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn the_combo_window_expires() {
+		let mut score = Score::default();
+		let mut combo = Combo::default();
+		apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+		apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+		count_down(&mut combo, COMBO_WINDOW / 2.0);
+		assert_eq!(combo.multiplier, 2);
+		count_down(&mut combo, COMBO_WINDOW / 2.0);
+		assert_eq!(combo.multiplier, 1);
+		assert_eq!(combo.time_left, 0.0);
+
+		apply_score_event(&mut score, &mut combo, ScoreEvent::EnemyKilled);
+		assert_eq!(combo.multiplier, 1);
+		assert_eq!(score.0, 4 * ENEMY_POINTS);
+	}
+}
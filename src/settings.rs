@@ -0,0 +1,86 @@
+use crate::cli::Args;
+use crate::components::MAX_PLAYERS;
+use crate::i18n::Language;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Saved in the browser, which has no command line or config file to set them with.
+#[cfg(target_arch = "wasm32")]
+static SETTINGS_PATH: &str = "settings.ron";
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		let settings = app
+			.world()
+			.get_resource::<Args>()
+			.map(Settings::from_args)
+			.unwrap_or_default();
+		#[cfg(target_arch = "wasm32")]
+		let settings = Settings::load().unwrap_or(settings);
+		app.insert_resource(settings);
+		#[cfg(target_arch = "wasm32")]
+		app.add_system(save_settings.system());
+	}
+}
+
+/// Player preferences that systems consult at runtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+	/// Disables camera shake and other effects that move the whole screen.
+	pub reduce_motion: bool,
+	/// 1, or 2 in local co-op and in networked games.
+	pub players: usize,
+	/// Whether missiles hurt the other player.
+	pub friendly_fire: bool,
+	/// Appends the stats of the session to the stats file on exit.
+	pub stats: bool,
+	/// Can be switched at runtime with the `language` console command.
+	pub language: Language,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Settings::from_args(&Args::default())
+	}
+}
+
+impl Settings {
+	fn from_args(args: &Args) -> Self {
+		Settings {
+			reduce_motion: args.reduce_motion,
+			players: if args.coop || args.host.is_some() || args.connect.is_some() {
+				MAX_PLAYERS
+			} else {
+				1
+			},
+			friendly_fire: args.friendly_fire,
+			stats: args.stats,
+			language: args.language.unwrap_or_default(),
+		}
+	}
+
+	#[cfg(target_arch = "wasm32")]
+	fn load() -> Option<Self> {
+		let text = crate::storage::read(SETTINGS_PATH)
+			.map_err(|e| info!("No settings loaded: {}", e))
+			.ok()?;
+		ron::de::from_str(&text)
+			.map_err(|e| warn!("Invalid settings in {}: {}", SETTINGS_PATH, e))
+			.ok()
+	}
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_settings(settings: Res<Settings>) {
+	if !settings.is_changed() || settings.is_added() {
+		return;
+	}
+	let result = ron::ser::to_string_pretty(&*settings, ron::ser::PrettyConfig::new())
+		.map_err(anyhow::Error::from)
+		.and_then(|text| crate::storage::write(SETTINGS_PATH, &text));
+	if let Err(e) = result {
+		error!("Failed to save settings to {}: {}", SETTINGS_PATH, e);
+	}
+}
@@ -0,0 +1,252 @@
+use crate::components::{Level, LevelType};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::settings::Settings;
+use crate::storage;
+use crate::AppState;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// JSON Lines file in the working directory (or in the browser), one [`SessionStats`] per line.
+pub static STATS_PATH: &str = "stats.jsonl";
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.insert_resource(SessionStats::start())
+			.add_event::<StatsEvent>()
+			.add_system(count_events.system())
+			.add_system_set(SystemSet::on_update(AppState::Game).with_system(time_levels.system()));
+		// After everything that may ask the app to exit. Pages are closed without an `AppExit`, so on
+		// the web `WebPlugin` saves the stats when the page is hidden instead.
+		#[cfg(not(target_arch = "wasm32"))]
+		app.add_system_to_stage(CoreStage::Last, write_stats.system());
+	}
+}
+
+/// Something worth counting for balancing the game.
+#[derive(Debug, Copy, Clone)]
+pub enum StatsEvent {
+	MissileFired,
+	/// A missile hit something.
+	Hit,
+	/// The player touched spikes, retracting ones included.
+	SpikeCollision,
+	/// A player's health ran out.
+	Death,
+	/// The player went through a portal to this level.
+	PortalUsed(LevelType),
+}
+
+/// What happened during one run of the game.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionStats {
+	/// Seconds since the Unix epoch.
+	pub started_at: u64,
+	pub missiles_fired: u32,
+	pub hits: u32,
+	pub spike_collisions: u32,
+	pub deaths: u32,
+	/// Seconds spent playing each level.
+	pub time_per_level: HashMap<LevelType, f32>,
+	/// How many times a portal to each level was taken.
+	pub portal_uses: HashMap<LevelType, u32>,
+}
+
+impl SessionStats {
+	pub fn start() -> Self {
+		SessionStats {
+			started_at: seconds_since_epoch(),
+			..Default::default()
+		}
+	}
+
+	fn add(&mut self, other: &SessionStats) {
+		self.missiles_fired += other.missiles_fired;
+		self.hits += other.hits;
+		self.spike_collisions += other.spike_collisions;
+		self.deaths += other.deaths;
+		for (l_type, time) in &other.time_per_level {
+			*self.time_per_level.entry(*l_type).or_default() += time;
+		}
+		for (l_type, uses) in &other.portal_uses {
+			*self.portal_uses.entry(*l_type).or_default() += uses;
+		}
+	}
+
+	pub fn save(&self) -> Result<(), anyhow::Error> {
+		storage::append_line(STATS_PATH, &serde_json::to_string(self)?)
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn seconds_since_epoch() -> u64 {
+	use std::time::{SystemTime, UNIX_EPOCH};
+
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+/// `SystemTime` panics in the browser.
+#[cfg(target_arch = "wasm32")]
+fn seconds_since_epoch() -> u64 {
+	(js_sys::Date::now() / 1000.0) as u64
+}
+
+fn count_events(mut events: EventReader<StatsEvent>, mut stats: ResMut<SessionStats>) {
+	for event in events.iter() {
+		match event {
+			StatsEvent::MissileFired => stats.missiles_fired += 1,
+			StatsEvent::Hit => stats.hits += 1,
+			StatsEvent::SpikeCollision => stats.spike_collisions += 1,
+			StatsEvent::Death => stats.deaths += 1,
+			StatsEvent::PortalUsed(l_type) => *stats.portal_uses.entry(*l_type).or_default() += 1,
+		}
+	}
+}
+
+fn time_levels(time: Res<Time>, level: Res<Level>, mut stats: ResMut<SessionStats>) {
+	*stats.time_per_level.entry(level.l_type).or_default() += time.delta_seconds();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_stats(
+	mut exit_events: EventReader<AppExit>,
+	settings: Res<Settings>,
+	stats: Res<SessionStats>,
+) {
+	if exit_events.iter().next().is_none() || !settings.stats {
+		return;
+	}
+	match stats.save() {
+		Ok(()) => info!("Saved session stats to {}", STATS_PATH),
+		Err(e) => error!("Failed to save session stats to {}: {}", STATS_PATH, e),
+	}
+}
+
+/// Prints the totals of every session in the stats file, for the `stats` subcommand.
+pub fn print_summary(path: &Path) -> Result<(), anyhow::Error> {
+	let file = std::io::BufReader::new(std::fs::File::open(path)?);
+	let (sessions, total) = summarize(path, file)?;
+	let played = total.time_per_level.values().sum::<f32>();
+	println!("Sessions: {}", sessions);
+	println!("Time played: {:.0} s", played);
+	println!("Missiles fired: {}", total.missiles_fired);
+	let accuracy = 100.0 * total.hits as f32 / total.missiles_fired.max(1) as f32;
+	println!("Hits: {} ({:.1}%)", total.hits, accuracy);
+	println!("Spike collisions: {}", total.spike_collisions);
+	println!("Deaths: {}", total.deaths);
+	println!("Time per level:");
+	for l_type in LevelType::ALL.iter() {
+		let time = total
+			.time_per_level
+			.get(l_type)
+			.copied()
+			.unwrap_or_default();
+		println!("    {:<10} {:.0} s", l_type.to_string(), time);
+	}
+	println!("Portal uses:");
+	for l_type in LevelType::ALL.iter() {
+		let uses = total.portal_uses.get(l_type).copied().unwrap_or_default();
+		println!("    {:<10} {}", l_type.to_string(), uses);
+	}
+	Ok(())
+}
+
+/// Adds up the sessions in `file`, skipping the invalid ones. `path` only names the file in the
+/// warnings. Returns the number of valid sessions and their totals.
+fn summarize(path: &Path, file: impl BufRead) -> Result<(u32, SessionStats), anyhow::Error> {
+	let mut sessions = 0;
+	let mut total = SessionStats::default();
+	for (idx, line) in file.lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		match serde_json::from_str::<SessionStats>(&line) {
+			Ok(session) => {
+				sessions += 1;
+				total.add(&session);
+			}
+			Err(e) => eprintln!(
+				"{}:{}: skipping invalid session: {}",
+				path.display(),
+				idx + 1,
+				e
+			),
+		}
+	}
+	Ok((sessions, total))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	fn session(deaths: u32, level1_time: f32, hub_portals: u32) -> SessionStats {
+		SessionStats {
+			started_at: 1_600_000_000,
+			missiles_fired: 10,
+			hits: 4,
+			spike_collisions: 2,
+			deaths,
+			time_per_level: vec![(LevelType::Level1, level1_time), (LevelType::Hub, 0.5)]
+				.into_iter()
+				.collect(),
+			portal_uses: vec![(LevelType::Hub, hub_portals)].into_iter().collect(),
+		}
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn sessions_survive_the_stats_format() {
+		let stats = session(1, 12.25, 3);
+		let line = serde_json::to_string(&stats).unwrap();
+		assert!(!line.contains('\n'));
+		// JSON object keys must be strings.
+		assert!(line.contains("\"Level1\":12.25"), "{}", line);
+		assert!(line.contains("\"Hub\":3"), "{}", line);
+		assert_eq!(serde_json::from_str::<SessionStats>(&line).unwrap(), stats);
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn sessions_are_summed() {
+		let contents = format!(
+			"{}\n\n{}\n",
+			serde_json::to_string(&session(1, 12.25, 3)).unwrap(),
+			serde_json::to_string(&session(2, 7.75, 1)).unwrap()
+		);
+		let (sessions, total) = summarize(Path::new("stats.jsonl"), contents.as_bytes()).unwrap();
+		assert_eq!(sessions, 2);
+		assert_eq!(total.missiles_fired, 20);
+		assert_eq!(total.hits, 8);
+		assert_eq!(total.spike_collisions, 4);
+		assert_eq!(total.deaths, 3);
+		assert_eq!(total.time_per_level[&LevelType::Level1], 20.0);
+		assert_eq!(total.time_per_level[&LevelType::Hub], 1.0);
+		assert_eq!(total.portal_uses[&LevelType::Hub], 4);
+		assert!(!total.portal_uses.contains_key(&LevelType::Level1));
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn invalid_sessions_are_skipped() {
+		let contents = format!(
+			"{}\n{{\"deaths\": \"many\"}}\nnot json\n{}\n",
+			serde_json::to_string(&session(1, 1.0, 0)).unwrap(),
+			serde_json::to_string(&session(1, 1.0, 0)).unwrap()
+		);
+		let (sessions, total) = summarize(Path::new("stats.jsonl"), contents.as_bytes()).unwrap();
+		assert_eq!(sessions, 2);
+		assert_eq!(total.deaths, 2);
+	}
+}
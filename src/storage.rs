@@ -0,0 +1,118 @@
+//! Small text files kept between runs: in the working directory, or in the local storage of the
+//! browser on the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(name: &str) -> Result<String, anyhow::Error> {
+	Ok(std::fs::read_to_string(name)?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(name: &str, contents: &str) -> Result<(), anyhow::Error> {
+	std::fs::write(name, contents)?;
+	Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn append_line(name: &str, line: &str) -> Result<(), anyhow::Error> {
+	use std::io::Write;
+
+	let mut file = std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(name)?;
+	writeln!(file, "{}", line)?;
+	Ok(())
+}
+
+/// Prefix of the local storage keys, so that the files don't clash with other pages on the same
+/// origin.
+#[cfg(target_arch = "wasm32")]
+static KEY_PREFIX: &str = "kod_jam/";
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, anyhow::Error> {
+	web_sys::window()
+		.ok_or_else(|| anyhow::anyhow!("no browser window"))?
+		.local_storage()
+		.map_err(|e| anyhow::anyhow!("{:?}", e))?
+		.ok_or_else(|| anyhow::anyhow!("local storage is disabled"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(name: &str) -> Result<String, anyhow::Error> {
+	local_storage()?
+		.get_item(&format!("{}{}", KEY_PREFIX, name))
+		.map_err(|e| anyhow::anyhow!("{:?}", e))?
+		.ok_or_else(|| anyhow::anyhow!("{} isn't saved", name))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(name: &str, contents: &str) -> Result<(), anyhow::Error> {
+	local_storage()?
+		.set_item(&format!("{}{}", KEY_PREFIX, name), contents)
+		.map_err(|e| anyhow::anyhow!("{:?}", e))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn append_line(name: &str, line: &str) -> Result<(), anyhow::Error> {
+	let mut contents = read(name).unwrap_or_default();
+	contents.push_str(line);
+	contents.push('\n');
+	write(name, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(target_arch = "wasm32")]
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	/// A file of the test's own, so that the tests don't overwrite the game's files.
+	#[cfg(not(target_arch = "wasm32"))]
+	fn file(name: &str) -> String {
+		let path = std::env::temp_dir().join(format!("kod_jam_{}_{}", std::process::id(), name));
+		let _ = std::fs::remove_file(&path);
+		path.to_string_lossy().into_owned()
+	}
+
+	#[cfg(target_arch = "wasm32")]
+	fn file(name: &str) -> String {
+		let name = format!("test/{}", name);
+		local_storage()
+			.unwrap()
+			.remove_item(&format!("{}{}", KEY_PREFIX, name))
+			.unwrap();
+		name
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn files_are_read_back() {
+		let name = file("written.txt");
+		assert!(read(&name).is_err());
+		write(&name, "first").unwrap();
+		write(&name, "second").unwrap();
+		assert_eq!(read(&name).unwrap(), "second");
+	}
+
+	#[test]
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+	fn lines_are_appended() {
+		let name = file("appended.txt");
+		append_line(&name, "first").unwrap();
+		append_line(&name, "second").unwrap();
+		assert_eq!(read(&name).unwrap(), "first\nsecond\n");
+	}
+
+	#[cfg(target_arch = "wasm32")]
+	#[wasm_bindgen_test]
+	fn keys_are_prefixed() {
+		let name = file("prefixed.txt");
+		write(&name, "contents").unwrap();
+		let key = format!("{}{}", KEY_PREFIX, name);
+		assert_eq!(
+			local_storage().unwrap().get_item(&key).unwrap().as_deref(),
+			Some("contents")
+		);
+	}
+}
@@ -0,0 +1,232 @@
+use crate::camera::screen_to_world;
+use crate::components::MainCamera;
+use crate::replay::SampleLabel;
+use crate::ui::ActiveStateScoped;
+use crate::AppState;
+use bevy::input::touch::{TouchInput, TouchPhase};
+use bevy::prelude::*;
+
+/// Joystick radius as a fraction of the shorter window side, so that it fits narrow windows.
+const JOYSTICK_SCALE: f32 = 0.15;
+const MIN_JOYSTICK_RADIUS: f32 = 24.0;
+/// Part of the window width, from the left, where a touch grabs the joystick instead of firing.
+const JOYSTICK_AREA: f32 = 0.4;
+
+pub struct TouchPlugin;
+
+impl Plugin for TouchPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<TouchControls>()
+			.init_resource::<TouchMaterials>()
+			// In every state, so that touching the menu already turns the controls on.
+			.add_system(update_touch_controls.system().before(SampleLabel))
+			.add_system_set(
+				SystemSet::on_enter(AppState::Game).with_system(spawn_joystick.system()),
+			)
+			.add_system_set(
+				SystemSet::on_resume(AppState::Game).with_system(spawn_joystick.system()),
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Game).with_system(update_joystick.system()),
+			);
+	}
+}
+
+/// On-screen joystick for moving and taps for firing, read by the input sampling like the other
+/// input devices.
+#[derive(Debug, Default)]
+pub struct TouchControls {
+	/// Turned on by the first touch.
+	pub enabled: bool,
+	/// Joystick deflection, at most 1 long.
+	pub movement: Vec2,
+	/// World position of a tap in this frame.
+	pub fire: Option<Vec2>,
+	joystick: Option<Joystick>,
+}
+
+/// The touch holding the joystick, in logical window pixels from the bottom left corner.
+#[derive(Debug)]
+struct Joystick {
+	id: u64,
+	/// Where the touch started. The joystick is centered there until it's released.
+	origin: Vec2,
+	position: Vec2,
+}
+
+/// Size and place of the joystick in the current window.
+struct Layout {
+	radius: f32,
+	/// Where the joystick rests while nobody holds it.
+	center: Vec2,
+	/// Touches starting left of this grab the joystick.
+	area_width: f32,
+}
+
+impl Layout {
+	fn new(window: &Window) -> Self {
+		let radius =
+			(window.width().min(window.height()) * JOYSTICK_SCALE).max(MIN_JOYSTICK_RADIUS);
+		let center = Vec2::splat(1.5 * radius);
+		Layout {
+			radius,
+			center,
+			area_width: (window.width() * JOYSTICK_AREA).max(center.x + radius),
+		}
+	}
+}
+
+struct TouchMaterials {
+	base: Handle<ColorMaterial>,
+	knob: Handle<ColorMaterial>,
+}
+
+impl FromWorld for TouchMaterials {
+	fn from_world(world: &mut World) -> Self {
+		let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+		TouchMaterials {
+			base: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.15).into()),
+			knob: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.4).into()),
+		}
+	}
+}
+
+struct JoystickBase;
+
+struct JoystickKnob;
+
+fn update_touch_controls(
+	windows: Res<Windows>,
+	mut touch_events: EventReader<TouchInput>,
+	mut controls: ResMut<TouchControls>,
+	cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+	let window = match windows.get_primary() {
+		Some(window) => window,
+		None => return,
+	};
+	let layout = Layout::new(window);
+	controls.fire = None;
+	for event in touch_events.iter() {
+		if !controls.enabled {
+			info!("Touch detected, showing the touch controls");
+			controls.enabled = true;
+		}
+		let holds_joystick = controls
+			.joystick
+			.as_ref()
+			.map_or(false, |joystick| joystick.id == event.id);
+		match event.phase {
+			TouchPhase::Started => {
+				if controls.joystick.is_none() && event.position.x < layout.area_width {
+					controls.joystick = Some(Joystick {
+						id: event.id,
+						origin: event.position,
+						position: event.position,
+					});
+				} else {
+					controls.fire = cameras.iter().next().map(|(camera, camera_transform)| {
+						screen_to_world(window, camera, camera_transform, event.position)
+					});
+				}
+			}
+			TouchPhase::Moved if holds_joystick => {
+				if let Some(joystick) = controls.joystick.as_mut() {
+					joystick.position = event.position;
+				}
+			}
+			TouchPhase::Ended | TouchPhase::Cancelled if holds_joystick => {
+				controls.joystick = None;
+			}
+			_ => {}
+		}
+	}
+
+	controls.movement = controls.joystick.as_ref().map_or(Vec2::ZERO, |joystick| {
+		let deflection = (joystick.position - joystick.origin) / layout.radius;
+		if deflection.length() > 1.0 {
+			deflection.normalize()
+		} else {
+			deflection
+		}
+	});
+}
+
+fn spawn_joystick(mut commands: Commands, materials: Res<TouchMaterials>) {
+	let hidden = || Visible {
+		is_visible: false,
+		is_transparent: true,
+	};
+	commands
+		.spawn_bundle(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				..Default::default()
+			},
+			material: materials.base.clone(),
+			visible: hidden(),
+			..Default::default()
+		})
+		.insert(JoystickBase)
+		.insert(ActiveStateScoped(AppState::Game))
+		.with_children(|parent| {
+			parent
+				.spawn_bundle(NodeBundle {
+					style: Style {
+						position_type: PositionType::Absolute,
+						..Default::default()
+					},
+					material: materials.knob.clone(),
+					visible: hidden(),
+					..Default::default()
+				})
+				.insert(JoystickKnob);
+		});
+}
+
+/// Sizes the joystick to the window and moves it under the touch holding it.
+#[allow(clippy::type_complexity)]
+fn update_joystick(
+	windows: Res<Windows>,
+	controls: Res<TouchControls>,
+	mut nodes: Query<
+		(&mut Style, &mut Visible, Option<&JoystickKnob>),
+		Or<(With<JoystickBase>, With<JoystickKnob>)>,
+	>,
+) {
+	let window = match windows.get_primary() {
+		Some(window) => window,
+		None => return,
+	};
+	let layout = Layout::new(window);
+	let center = controls
+		.joystick
+		.as_ref()
+		.map_or(layout.center, |joystick| joystick.origin);
+	let knob_radius = layout.radius / 2.0;
+
+	for (mut style, mut visible, knob) in nodes.iter_mut() {
+		let (bottom_left, radius) = match knob {
+			// Relative to the base.
+			Some(_) => (
+				Vec2::splat(layout.radius - knob_radius) + controls.movement * layout.radius,
+				knob_radius,
+			),
+			None => (center - Vec2::splat(layout.radius), layout.radius),
+		};
+		let size = Size::new(Val::Px(2.0 * radius), Val::Px(2.0 * radius));
+		let position = Rect {
+			left: Val::Px(bottom_left.x),
+			bottom: Val::Px(bottom_left.y),
+			..Default::default()
+		};
+		// Only touch what changed, so that the UI isn't laid out again every frame.
+		if visible.is_visible != controls.enabled {
+			visible.is_visible = controls.enabled;
+		}
+		if style.size != size || style.position != position {
+			style.size = size;
+			style.position = position;
+		}
+	}
+}
@@ -0,0 +1,54 @@
+use crate::AppState;
+use bevy::prelude::*;
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.add_startup_system(spawn_ui_camera.system())
+			// After the state transitions of the frame are done.
+			.add_system_to_stage(CoreStage::PostUpdate, despawn_state_scoped.system());
+	}
+}
+
+/// The only UI camera. Every UI node in the game is drawn by it.
+pub struct UiCamera;
+
+/// Despawned with its children once the game leaves this state. A state that's only paused by a
+/// pushed one keeps its entities, and the game over screen keeps the game's world.
+#[derive(Debug, Copy, Clone)]
+pub struct StateScoped(pub AppState);
+
+/// Like [`StateScoped`], but also despawned while the state is paused by a pushed one, or behind
+/// the game over screen.
+#[derive(Debug, Copy, Clone)]
+pub struct ActiveStateScoped(pub AppState);
+
+fn spawn_ui_camera(mut commands: Commands) {
+	commands
+		.spawn_bundle(UiCameraBundle::default())
+		.insert(UiCamera);
+}
+
+fn despawn_state_scoped(
+	mut commands: Commands,
+	state: Res<State<AppState>>,
+	scoped: Query<(Entity, &StateScoped)>,
+	active_scoped: Query<(Entity, &ActiveStateScoped)>,
+) {
+	if !state.is_changed() {
+		return;
+	}
+	let current = *state.current();
+	for (entity, StateScoped(scope)) in scoped.iter() {
+		let game_over = current == AppState::GameOver && *scope == AppState::Game;
+		if *scope != current && !game_over && !state.inactives().contains(scope) {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+	for (entity, ActiveStateScoped(scope)) in active_scoped.iter() {
+		if *scope != current {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+}
@@ -0,0 +1,305 @@
+use crate::cli::MIN_WINDOW_SIZE;
+use crate::settings::Settings;
+use crate::stats::{SessionStats, STATS_PATH};
+use crate::touch::TouchControls;
+use crate::AppState;
+use bevy::input::touch::{TouchInput, TouchPhase};
+use bevy::prelude::*;
+use bevy::window::{CursorMoved, WindowFocused};
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::convert::FromWasmAbi;
+use wasm_bindgen::JsCast;
+
+/// Selector of the canvas in `index.html` the game draws to.
+pub static CANVAS: &str = "#bevy";
+
+pub struct WebPlugin;
+
+impl Plugin for WebPlugin {
+	fn build(&self, app: &mut AppBuilder) {
+		app.init_resource::<PointerMovement>()
+			.init_resource::<BrowserTouches>()
+			.init_resource::<PageStats>()
+			.add_startup_system(listen_to_canvas.system())
+			.add_startup_system(listen_to_page.system())
+			// Before anything is counted in a session that was saved.
+			.add_system_to_stage(CoreStage::First, track_stats.system())
+			// Before the cursor position and the touches are read by anything else.
+			.add_system_to_stage(CoreStage::First, move_locked_cursor.system())
+			.add_system_to_stage(CoreStage::First, send_touches.system())
+			.add_system(fit_canvas.system())
+			.add_system(release_input_on_blur.system())
+			.add_system_set(SystemSet::on_update(AppState::Game).with_system(lock_pointer.system()))
+			.add_system_set(
+				SystemSet::on_exit(AppState::Game).with_system(unlock_pointer.system()),
+			);
+	}
+}
+
+/// Mouse movement the browser reported since the last frame. The cursor position stops changing
+/// while the pointer is locked, so the crosshair is moved by this instead.
+#[derive(Default)]
+struct PointerMovement(Arc<Mutex<Vec2>>);
+
+/// Touches the browser reported since the last frame. Winit doesn't report them on the web.
+#[derive(Default)]
+struct BrowserTouches(Arc<Mutex<Vec<TouchInput>>>);
+
+/// The stats of the session as they are saved when the page is hidden. The browser may close the
+/// page at any time after that, without running another frame.
+#[derive(Default)]
+struct PageStats(Arc<Mutex<UnsavedStats>>);
+
+#[derive(Default)]
+struct UnsavedStats {
+	/// `None` if the stats aren't saved or were saved already.
+	stats: Option<SessionStats>,
+	/// The stats were saved while the page was hidden, so the game continues in a new session.
+	saved: bool,
+}
+
+fn canvas() -> Option<web_sys::Element> {
+	web_sys::window()?
+		.document()?
+		.query_selector(CANVAS)
+		.ok()
+		.flatten()
+}
+
+fn pointer_locked() -> bool {
+	web_sys::window()
+		.and_then(|window| window.document())
+		.and_then(|document| document.pointer_lock_element())
+		.is_some()
+}
+
+/// Calls `listener` with every `event_type` event of `target`, for as long as the page is open.
+fn listen<E>(target: &web_sys::EventTarget, event_type: &str, listener: impl FnMut(E) + 'static)
+where
+	E: FromWasmAbi + 'static,
+{
+	let listener = Closure::wrap(Box::new(listener) as Box<dyn FnMut(E)>);
+	if let Err(e) =
+		target.add_event_listener_with_callback(event_type, listener.as_ref().unchecked_ref())
+	{
+		error!("Failed to listen to {} events: {:?}", event_type, e);
+	}
+	listener.forget();
+}
+
+fn listen_to_canvas(movement: Res<PointerMovement>, touches: Res<BrowserTouches>) {
+	let canvas = match canvas() {
+		Some(canvas) => canvas,
+		None => {
+			error!("No canvas matching {} to listen to", CANVAS);
+			return;
+		}
+	};
+
+	let total = movement.0.clone();
+	listen(&canvas, "mousemove", move |event: web_sys::MouseEvent| {
+		// The browser's y axis points down.
+		let moved = Vec2::new(event.movement_x() as f32, -event.movement_y() as f32);
+		*total.lock().unwrap() += moved;
+	});
+
+	let phases = [
+		("touchstart", TouchPhase::Started),
+		("touchmove", TouchPhase::Moved),
+		("touchend", TouchPhase::Ended),
+		("touchcancel", TouchPhase::Cancelled),
+	];
+	for (event_type, phase) in phases.iter().copied() {
+		let pending = touches.0.clone();
+		let target = canvas.clone();
+		listen(&canvas, event_type, move |event: web_sys::TouchEvent| {
+			// From the bottom left corner of the canvas, like the cursor position.
+			let rect = target.get_bounding_client_rect();
+			let changed = event.changed_touches();
+			let mut pending = pending.lock().unwrap();
+			for touch in (0..changed.length()).filter_map(|idx| changed.get(idx)) {
+				pending.push(TouchInput {
+					phase,
+					position: Vec2::new(
+						(f64::from(touch.client_x()) - rect.left()) as f32,
+						(rect.bottom() - f64::from(touch.client_y())) as f32,
+					),
+					force: None,
+					id: touch.identifier() as u64,
+				});
+			}
+		});
+	}
+}
+
+/// Saves the stats when the page is hidden or closed. Mobile browsers may close hidden pages without
+/// any further event.
+fn listen_to_page(page_stats: Res<PageStats>) {
+	let (browser, document) = match web_sys::window()
+		.and_then(|browser| browser.document().map(|document| (browser, document)))
+	{
+		Some(page) => page,
+		None => {
+			error!("No page to save the session stats on closing");
+			return;
+		}
+	};
+
+	let unsaved = page_stats.0.clone();
+	let hidden = document.clone();
+	listen(&document, "visibilitychange", move |_: web_sys::Event| {
+		if hidden.hidden() {
+			save_stats(&unsaved);
+		}
+	});
+	let unsaved = page_stats.0.clone();
+	listen(&browser, "pagehide", move |_: web_sys::Event| {
+		save_stats(&unsaved)
+	});
+}
+
+fn save_stats(unsaved: &Mutex<UnsavedStats>) {
+	let mut unsaved = unsaved.lock().unwrap();
+	let stats = match unsaved.stats.take() {
+		Some(stats) => stats,
+		None => return,
+	};
+	unsaved.saved = true;
+	match stats.save() {
+		Ok(()) => info!("Saved session stats to {}", STATS_PATH),
+		Err(e) => error!("Failed to save session stats to {}: {}", STATS_PATH, e),
+	}
+}
+
+/// Keeps the copy of the stats saved by the page up to date, and starts a new session once they
+/// were saved.
+fn track_stats(
+	settings: Res<Settings>,
+	mut stats: ResMut<SessionStats>,
+	page_stats: Res<PageStats>,
+) {
+	let mut unsaved = page_stats.0.lock().unwrap();
+	if std::mem::take(&mut unsaved.saved) {
+		*stats = SessionStats::start();
+	}
+	if stats.is_changed() || settings.is_changed() {
+		unsaved.stats = Some(stats.clone()).filter(|_| settings.stats);
+	}
+}
+
+fn send_touches(touches: Res<BrowserTouches>, mut touch_events: EventWriter<TouchInput>) {
+	for touch in touches.0.lock().unwrap().drain(..) {
+		touch_events.send(touch);
+	}
+}
+
+fn move_locked_cursor(
+	movement: Res<PointerMovement>,
+	mut windows: ResMut<Windows>,
+	mut cursor_events: EventWriter<CursorMoved>,
+) {
+	let moved = std::mem::take(&mut *movement.0.lock().unwrap());
+	if moved == Vec2::ZERO || !pointer_locked() {
+		return;
+	}
+	let window = match windows.get_primary_mut() {
+		Some(window) => window,
+		None => return,
+	};
+	let size = Vec2::new(window.width(), window.height());
+	let position = window.cursor_position().unwrap_or(size / 2.0) + moved;
+	let position = position.clamp(Vec2::ZERO, size);
+	window.update_cursor_position_from_backend(Some(position));
+	cursor_events.send(CursorMoved {
+		id: window.id(),
+		position,
+	});
+}
+
+/// Locks the pointer to the canvas on a click, so that aiming doesn't stop at the page edges.
+fn lock_pointer(mouse_input: Res<Input<MouseButton>>, touch: Res<TouchControls>) {
+	// Taps are followed by clicks, but there's no pointer to lock.
+	if touch.enabled || !mouse_input.just_pressed(MouseButton::Left) || pointer_locked() {
+		return;
+	}
+	if let Some(canvas) = canvas() {
+		canvas.request_pointer_lock();
+	}
+}
+
+fn unlock_pointer() {
+	if let Some(document) = web_sys::window().and_then(|window| window.document()) {
+		document.exit_pointer_lock();
+	}
+}
+
+/// Resizes the canvas to the browser window, down to the smallest window size.
+fn fit_canvas(mut windows: ResMut<Windows>) {
+	let browser = match web_sys::window() {
+		Some(browser) => browser,
+		None => return,
+	};
+	let size = |value: Result<wasm_bindgen::JsValue, _>| {
+		value
+			.ok()
+			.and_then(|value| value.as_f64())
+			.map(|value| value as f32)
+	};
+	let (width, height) = match (size(browser.inner_width()), size(browser.inner_height())) {
+		(Some(width), Some(height)) => {
+			(width.max(MIN_WINDOW_SIZE.0), height.max(MIN_WINDOW_SIZE.1))
+		}
+		_ => return,
+	};
+	if let Some(window) = windows.get_primary_mut() {
+		if window.width() != width || window.height() != height {
+			window.set_resolution(width, height);
+		}
+	}
+}
+
+/// The browser doesn't report the keys released while the page is in the background, so they
+/// would stay pressed.
+fn release_input_on_blur(
+	mut focus_events: EventReader<WindowFocused>,
+	mut kb_input: ResMut<Input<KeyCode>>,
+	mut mouse_input: ResMut<Input<MouseButton>>,
+) {
+	if !focus_events.iter().any(|event| !event.focused) {
+		return;
+	}
+	let keys = kb_input.get_pressed().copied().collect::<Vec<_>>();
+	for key in keys {
+		kb_input.release(key);
+	}
+	let buttons = mouse_input.get_pressed().copied().collect::<Vec<_>>();
+	for button in buttons {
+		mouse_input.release(button);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+	// Local storage and the document only exist in a browser.
+	wasm_bindgen_test_configure!(run_in_browser);
+
+	#[wasm_bindgen_test]
+	fn the_page_has_the_canvas() {
+		let html = include_str!("../index.html");
+		let id = CANVAS.trim_start_matches('#');
+		assert!(html.contains(&format!("<canvas id=\"{}\"", id)));
+
+		let page = web_sys::window()
+			.and_then(|window| window.document())
+			.and_then(|document| document.document_element())
+			.unwrap();
+		page.insert_adjacent_html("beforeend", &format!("<canvas id=\"{}\"></canvas>", id))
+			.unwrap();
+		assert_eq!(canvas().unwrap().id(), id);
+		assert!(!pointer_locked());
+	}
+}